@@ -0,0 +1,147 @@
+//! A builder for creating survey data without a Survex file
+
+use crate::data::SurveyData;
+use crate::error::BuildError;
+use crate::leg::Leg;
+use crate::point::Point;
+use crate::station::StationFlags;
+
+/// Builds a [`SurveyData`] instance from stations and legs given in code, such as for generating
+/// synthetic surveys or converting data from other formats
+///
+/// Stations are referred to by their labels, and the length of each leg is calculated from the
+/// coordinates of the stations it connects:
+///
+/// ```rust
+/// use survex_rs::builder::SurveyBuilder;
+/// use survex_rs::point::Point;
+/// use survex_rs::station::StationFlags;
+///
+/// let data = SurveyBuilder::new()
+///     .title("Example cave")
+///     .add_station("cave.1", Point::new(0.0, 0.0, 0.0), StationFlags::ENTRANCE)
+///     .add_station("cave.2", Point::new(3.0, 4.0, 0.0), StationFlags::UNDERGROUND)
+///     .add_leg("cave.1", "cave.2")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(data.total_length(true, true), 5.0);
+/// ```
+///
+/// Any problem with the stations or legs, such as a leg to a station which has not been added, is
+/// reported by [`build`][`SurveyBuilder::build`].
+#[derive(Default)]
+pub struct SurveyBuilder {
+    data: SurveyData,
+    error: Option<BuildError>,
+}
+
+impl SurveyBuilder {
+    /// Create a new [`SurveyBuilder`] with no stations or legs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the title of the survey.
+    pub fn title(mut self, title: &str) -> Self {
+        self.data.title = String::from(title);
+        self
+    }
+
+    /// Add a station with the given label, coordinates and flags. Each label may only be added
+    /// once.
+    pub fn add_station(mut self, label: &str, coords: Point, flags: StationFlags) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        if self.data.get_by_label(label).is_some() {
+            self.error = Some(BuildError::DuplicateStation(String::from(label)));
+            return self;
+        }
+
+        let (station, _) = self.data.add_or_update(coords, label);
+        {
+            let mut station = station.borrow_mut();
+            station.flags = flags;
+            station.raw_flags = flags.bits() as u8;
+        }
+        self
+    }
+
+    /// Add a leg between the stations with the given labels, which must already have been added.
+    /// The length of the leg is the distance between the stations, and it has the default style
+    /// and no flags.
+    pub fn add_leg(mut self, from: &str, to: &str) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let Some(from) = self.data.get_by_label(from) else {
+            self.error = Some(BuildError::MissingStation(String::from(from)));
+            return self;
+        };
+        let Some(to) = self.data.get_by_label(to) else {
+            self.error = Some(BuildError::MissingStation(String::from(to)));
+            return self;
+        };
+
+        let (from, to) = (from.borrow(), to.borrow());
+        let leg = Leg::new(from.coords.distance(&to.coords));
+        self.data.graph.add_edge(from.index, to.index, leg);
+        self
+    }
+
+    /// Build the [`SurveyData`] instance, or return the first error encountered while adding
+    /// stations and legs.
+    pub fn build(self) -> Result<SurveyData, BuildError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::ENTRANCE)
+            .add_station("a.2", Point::new(3.0, 4.0, 0.0), StationFlags::UNDERGROUND)
+            .add_station(
+                "a.3",
+                Point::new(3.0, 4.0, -12.0),
+                StationFlags::UNDERGROUND,
+            )
+            .add_leg("a.1", "a.2")
+            .add_leg("a.2", "a.3")
+            .build()
+            .unwrap();
+
+        assert_eq!(data.stations.len(), 3);
+        assert_eq!(data.graph.edge_count(), 2);
+        assert_eq!(data.total_length(true, true), 17.0);
+        assert!(data.get_by_label("a.1").unwrap().borrow().entrance());
+        assert_eq!(
+            data.shortest_path("a.1", "a.3").map(|(length, _)| length),
+            Some(17.0)
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let result = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.1", Point::new(1.0, 0.0, 0.0), StationFlags::empty())
+            .build();
+        assert!(matches!(result, Err(BuildError::DuplicateStation(label)) if label == "a.1"));
+
+        let result = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .add_leg("a.3", "a.1")
+            .build();
+        assert!(matches!(result, Err(BuildError::MissingStation(label)) if label == "a.2"));
+    }
+}
@@ -1,19 +1,244 @@
 //! Data structures to represent processed Survex data
 
-use crate::station::{Point, Station};
-use petgraph::graph::{NodeIndex, UnGraph};
-use std::cell::RefCell;
+use crate::fuzzy;
+use crate::leg::{Leg, LegStyle, LoopError};
+use crate::point::{BoundingBox, LengthUnit, Point, Projection};
+use crate::read::coordinate_key;
+use crate::spatial::KdTree;
+use crate::station::Station;
+use log::warn;
+use petgraph::algo::{astar, connected_components, dijkstra};
+use petgraph::graph::{EdgeIndex, NodeIndex, UnGraph};
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use std::cell::{Ref, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::rc::Rc;
+use std::time::SystemTime;
 
 pub type Stations = Vec<RefStation>;
 pub type RefStation = Rc<RefCell<Station>>;
-pub type StationGraph = UnGraph<String, f64>;
+pub type StationGraph = UnGraph<String, Leg>;
+
+/// The minimum inclination, in degrees from horizontal, for a leg to be considered a pitch by
+/// [`SurveyData::pitches`].
+pub const PITCH_MIN_INCLINATION: f64 = 70.0;
+
+/// The maximum number of bins which [`SurveyData::length_histogram`] and
+/// [`SurveyData::length_depth_profile`] will return. This guards against a tiny bin width
+/// exhausting memory.
+pub const MAX_BINS: usize = 1_000_000;
 
 /// Handles the creation and management of stations, as well as holding the
 /// [`graph`][`petgraph::graph::Graph`] of stations.
 pub struct SurveyData {
     pub stations: Stations,
     pub graph: StationGraph,
+    /// The title of the survey, as stored in the Survex file. This will be empty if the file
+    /// does not specify a title.
+    pub title: String,
+    /// The coordinate system of the survey data, as a string suitable for passing to PROJ (such
+    /// as `EPSG:32630`). This will be [`None`] if the Survex file does not specify one.
+    pub coordinate_system: Option<String>,
+    /// The datestamp of the Survex file as stored in the file, which is either a human readable
+    /// date or, for newer files, `@` followed by the number of seconds since the Unix epoch. This
+    /// will be [`None`] if the file has no date information.
+    pub datestamp: Option<String>,
+    /// The time at which the Survex file was produced, if it could be determined from the
+    /// datestamp.
+    pub timestamp: Option<SystemTime>,
+    /// The version of the Survex file format. Negative values indicate files which are not
+    /// Survex .3d files, such as -1 for a Survex .pos file.
+    pub format_version: i32,
+    /// The character which separates the components of station labels, such as `.` in
+    /// `cave.upper.1`, as stored in the Survex file.
+    pub separator: char,
+    /// Whether the Survex file is an extended elevation, in which the survey has been flattened
+    /// onto a single vertical plane. The coordinates of stations in an extended elevation do not
+    /// reflect their true position, so distances between stations are not meaningful.
+    pub extended_elevation: bool,
+    /// The unit of the station coordinates. The Survex library always gives coordinates in
+    /// metres, unless the coordinate system of the survey uses feet, in which case coordinates
+    /// are in feet and all distances and lengths reported by this struct are also in feet.
+    pub units: LengthUnit,
+    /// Stations which were found to share a label with an existing station at different
+    /// coordinates, stored as the label followed by the coordinates of the existing station and
+    /// the coordinates of the conflicting station. This is only populated when duplicate label
+    /// detection is enabled via [`LoadOptions`][`crate::read::LoadOptions`].
+    pub label_conflicts: Vec<(String, Point, Point)>,
+    /// The loop closure error statistics of each traverse, as recorded in the Survex file. These
+    /// are not copied into new instances created from a subset of this one, such as by
+    /// [`subsurvey`][`SurveyData::subsurvey`].
+    pub loop_errors: Vec<LoopError>,
+    /// An index of station labels to their index in the graph, used to speed up
+    /// [`get_by_label`][`SurveyData::get_by_label`]. This is kept up to date by the methods which
+    /// add or relabel stations.
+    label_index: HashMap<String, NodeIndex>,
+    /// A spatial index of station coordinates, used by
+    /// [`nearest_station`][`SurveyData::nearest_station`] and
+    /// [`stations_within`][`SurveyData::stations_within`]. This is built on first use and
+    /// discarded whenever stations are added or moved.
+    spatial_index: RefCell<Option<KdTree>>,
+    /// The total lengths returned by [`total_length`][`SurveyData::total_length`] for each
+    /// combination of its arguments, computed on first use and discarded whenever legs are added
+    /// or their lengths change.
+    length_cache: RefCell<Option<Cached<[f64; 4]>>>,
+    /// The result of [`bounding_box`][`SurveyData::bounding_box`], computed on first use and
+    /// discarded whenever stations are added or moved.
+    bounding_box_cache: RefCell<Option<Cached<Option<BoundingBox>>>>,
+}
+
+/// A value computed from a [`SurveyData`] instance, along with the number of legs or stations it
+/// was computed from. This allows a cached value to be recomputed if legs or stations have been
+/// added directly to the public fields since it was computed.
+#[derive(Debug, Clone, Copy)]
+struct Cached<T> {
+    count: usize,
+    value: T,
+}
+
+/// The representation of a [`SurveyData`] instance used by [`SurveyData::to_json`] and
+/// [`SurveyData::from_json`]. Legs are stored as the graph indices of the stations they connect,
+/// followed by the leg itself.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedSurveyData {
+    title: String,
+    coordinate_system: Option<String>,
+    datestamp: Option<String>,
+    timestamp: Option<SystemTime>,
+    format_version: i32,
+    separator: char,
+    #[serde(default)]
+    extended_elevation: bool,
+    #[serde(default)]
+    units: LengthUnit,
+    label_conflicts: Vec<(String, Point, Point)>,
+    #[serde(default)]
+    loop_errors: Vec<LoopError>,
+    stations: Vec<Station>,
+    legs: Vec<(usize, usize, Leg)>,
+}
+
+/// Summary statistics for a connected component of the survey graph
+///
+/// A connected component is a group of stations which are all connected to each other by legs,
+/// such as a single cave system. See [`SurveyData::component_stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComponentStats {
+    /// The number of stations in the component.
+    pub stations: usize,
+    /// The number of legs in the component.
+    pub legs: usize,
+    /// The total length of the legs in the component, in metres.
+    pub length: f64,
+    /// The bounding box of the stations in the component.
+    pub bounding_box: BoundingBox,
+}
+
+/// The walls of the passage along a single leg, reconstructed from LRUD measurements
+///
+/// See [`SurveyData::passage_walls`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassagePolygon {
+    /// The index of the first station of the leg.
+    pub from: NodeIndex,
+    /// The index of the second station of the leg.
+    pub to: NodeIndex,
+    /// The corners of the cross-section at the first station, ordered top left, top right,
+    /// bottom right and bottom left.
+    pub start: [Point; 4],
+    /// The corners of the cross-section at the second station, in the same order as
+    /// [`start`][`PassagePolygon::start`].
+    pub end: [Point; 4],
+}
+
+impl PassagePolygon {
+    /// The outline of the passage in plan, ordered left wall at the first station, left wall at
+    /// the second station, right wall at the second station and right wall at the first station.
+    /// The corners at the top of the passage are used, so the z coordinates should be ignored.
+    pub fn plan(&self) -> [Point; 4] {
+        [self.start[0], self.end[0], self.end[1], self.start[1]]
+    }
+
+    /// The outline of the passage in section, ordered roof at the first station, roof at the
+    /// second station, floor at the second station and floor at the first station. The corners
+    /// on the left wall are used, as both walls coincide when viewed side on.
+    pub fn section(&self) -> [Point; 4] {
+        [self.start[0], self.end[0], self.end[3], self.start[3]]
+    }
+}
+
+/// Calculate the corners of a vertical cross-section through a passage at `point`, for a leg
+/// heading in the given `direction`. The measurements are given in the order left, right, up,
+/// down, and the corners are returned ordered top left, top right, bottom right and bottom left.
+///
+/// Up and down are offsets along the z axis. Left and right are offsets along the horizontal unit
+/// vector at right angles to the leg, `(dy, -dx, 0) / sqrt(dx² + dy²)` for a leg direction of
+/// `(dx, dy, dz)`, which points to the right when looking along the leg. Vertical legs have no
+/// horizontal direction, so the x axis is used for left and right instead.
+pub(crate) fn cross_section(point: Point, direction: Point, lrud: [f64; 4]) -> [Point; 4] {
+    let [left, right, up, down] = lrud;
+    let horizontal = direction.x.hypot(direction.y);
+    let across = if horizontal > 0.0 {
+        Point::new(direction.y / horizontal, -direction.x / horizontal, 0.0)
+    } else {
+        Point::new(1.0, 0.0, 0.0)
+    };
+    let vertical = Point::new(0.0, 0.0, 1.0);
+    [
+        point + across * -left + vertical * up,
+        point + across * right + vertical * up,
+        point + across * right + vertical * -down,
+        point + across * -left + vertical * -down,
+    ]
+}
+
+/// The result of searching for a station by part of its label
+///
+/// See [`SurveyData::find_by_label_part`].
+#[derive(Debug, Clone)]
+pub enum LabelMatch {
+    /// No station label contains the search term.
+    None,
+    /// A station's label is exactly equal to the search term.
+    Exact(RefStation),
+    /// Exactly one station label contains the search term, but is not equal to it.
+    Unique(RefStation),
+    /// More than one station label contains the search term, and none are equal to it. The
+    /// matching stations are listed in the order of the stations vector.
+    Ambiguous(Vec<RefStation>),
+}
+
+/// How [`SurveyData::merge`] handles stations which share a label with a station already in the
+/// survey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelCollision {
+    /// Stations which share a label are welded together into a single station, keeping the
+    /// existing station as-is. This is how separate Survex files are joined at their common
+    /// exported stations.
+    Weld,
+    /// Every station label from the merged survey is prefixed with the given string and the
+    /// [separator][`SurveyData::separator`] of this survey, such as `system2.cave.1`, so that the
+    /// two surveys are kept apart. Anonymous stations are not relabelled. If a prefixed label still
+    /// matches an existing station, the stations are welded together as with
+    /// [`Weld`][`LabelCollision::Weld`].
+    Prefix(String),
+}
+
+/// A possible problem with the data in a survey
+///
+/// See [`SurveyData::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The station with the given label has no legs connected to it, which is sometimes called an
+    /// orphan station. See [`SurveyData::isolated_stations`].
+    IsolatedStation(String),
+    /// The station with the given label has a coordinate which is NaN.
+    NanCoordinates(String),
+    /// The stations with the given labels all have exactly the same coordinates. The labels are
+    /// listed in the order of the stations vector.
+    DuplicateCoordinates(Vec<String>),
 }
 
 impl Default for SurveyData {
@@ -26,49 +251,337 @@ impl Default for SurveyData {
 impl SurveyData {
     /// Create an empty [`SurveyData`] instance with no stations or connections. This method should
     /// not be used directly. Instead, create a [`SurveyData`] instance from a Survex file using the
-    /// [`load_from_path`][`crate::read::load_from_path`] helper function.
+    /// [`load_from_path`][`crate::read::load_from_path`] helper function, or from stations and
+    /// legs given in code using a [`SurveyBuilder`][`crate::builder::SurveyBuilder`].
     pub fn new() -> Self {
         Self {
             stations: Vec::new(),
             graph: StationGraph::new_undirected(),
+            title: String::new(),
+            coordinate_system: None,
+            datestamp: None,
+            timestamp: None,
+            format_version: 0,
+            separator: '.',
+            extended_elevation: false,
+            units: LengthUnit::Metres,
+            label_conflicts: Vec::new(),
+            loop_errors: Vec::new(),
+            label_index: HashMap::new(),
+            spatial_index: RefCell::new(None),
+            length_cache: RefCell::new(None),
+            bounding_box_cache: RefCell::new(None),
+        }
+    }
+
+    /// Serialize the survey to a self-contained JSON document, including the header information,
+    /// every station and every leg. The survey can be loaded again with
+    /// [`from_json`][`SurveyData::from_json`].
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let serialized = SerializedSurveyData {
+            title: self.title.clone(),
+            coordinate_system: self.coordinate_system.clone(),
+            datestamp: self.datestamp.clone(),
+            timestamp: self.timestamp,
+            format_version: self.format_version,
+            separator: self.separator,
+            extended_elevation: self.extended_elevation,
+            units: self.units,
+            label_conflicts: self.label_conflicts.clone(),
+            loop_errors: self.loop_errors.clone(),
+            stations: self
+                .stations
+                .iter()
+                .map(|station| station.borrow().clone())
+                .collect(),
+            legs: self
+                .graph
+                .edge_references()
+                .map(|edge| (edge.source().index(), edge.target().index(), *edge.weight()))
+                .collect(),
+        };
+        serde_json::to_string(&serialized)
+    }
+
+    /// Load a survey from a JSON document produced by [`to_json`][`SurveyData::to_json`]. The
+    /// graph is rebuilt from the stations and legs in the document, and stations keep the indices
+    /// they were serialized with.
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> Result<SurveyData, serde_json::Error> {
+        use serde::de::Error;
+
+        let serialized: SerializedSurveyData = serde_json::from_str(s)?;
+        let mut data = SurveyData::new();
+        data.title = serialized.title;
+        data.coordinate_system = serialized.coordinate_system;
+        data.datestamp = serialized.datestamp;
+        data.timestamp = serialized.timestamp;
+        data.format_version = serialized.format_version;
+        data.separator = serialized.separator;
+        data.extended_elevation = serialized.extended_elevation;
+        data.units = serialized.units;
+        data.label_conflicts = serialized.label_conflicts;
+        data.loop_errors = serialized.loop_errors;
+
+        for station in serialized.stations {
+            let expected = station.index;
+            let (_, index) = data.push_station(station);
+            if index != expected {
+                return Err(serde_json::Error::custom(format!(
+                    "station index {} is out of order",
+                    expected.index()
+                )));
+            }
+        }
+
+        let node_count = data.graph.node_count();
+        for (a, b, leg) in serialized.legs {
+            if a >= node_count || b >= node_count {
+                return Err(serde_json::Error::custom(format!(
+                    "leg refers to missing station index {}",
+                    a.max(b)
+                )));
+            }
+            data.graph
+                .add_edge(NodeIndex::new(a), NodeIndex::new(b), leg);
+        }
+        Ok(data)
+    }
+
+    /// Retrieve the coordinate system of the survey data as a string slice, or [`None`] if the
+    /// Survex file did not specify one.
+    pub fn coordinate_system(&self) -> Option<&str> {
+        self.coordinate_system.as_deref()
+    }
+
+    /// Reproject a point from the [coordinate system][`SurveyData::coordinate_system`] of the
+    /// survey to WGS84, returning its latitude and longitude in degrees. This requires the `proj`
+    /// feature.
+    ///
+    /// Returns [`None`] if the survey has no coordinate system, the coordinate system is not
+    /// understood by PROJ, or the point could not be reprojected. The z coordinate is ignored.
+    /// When reprojecting every station, use
+    /// [`stations_to_lat_lon`][`SurveyData::stations_to_lat_lon`] instead, which only sets up the
+    /// transformation once.
+    #[cfg(feature = "proj")]
+    pub fn to_lat_lon(&self, p: &Point) -> Option<(f64, f64)> {
+        let proj = self.lat_lon_transform()?;
+        let (lon, lat) = proj.convert((p.x, p.y)).ok()?;
+        Some((lat, lon))
+    }
+
+    /// Reproject every station to WGS84, as with [`to_lat_lon`][`SurveyData::to_lat_lon`],
+    /// returning the latitude and longitude of each station in the same order as the stations
+    /// vector. This requires the `proj` feature.
+    ///
+    /// Returns [`None`] if the survey has no usable coordinate system or any station could not be
+    /// reprojected.
+    #[cfg(feature = "proj")]
+    pub fn stations_to_lat_lon(&self) -> Option<Vec<(f64, f64)>> {
+        let proj = self.lat_lon_transform()?;
+        self.stations
+            .iter()
+            .map(|station| {
+                let coords = station.borrow().coords;
+                let (lon, lat) = proj.convert((coords.x, coords.y)).ok()?;
+                Some((lat, lon))
+            })
+            .collect()
+    }
+
+    /// Helper method to create a transformation from the coordinate system of the survey to
+    /// WGS84. The transformation takes and returns coordinates in (x, y) order, so its output is
+    /// (longitude, latitude).
+    #[cfg(feature = "proj")]
+    pub(crate) fn lat_lon_transform(&self) -> Option<proj::Proj> {
+        let cs = self.coordinate_system()?;
+        proj::Proj::new_known_crs(cs, "EPSG:4326", None).ok()
+    }
+
+    /// Retrieve the datestamp of the Survex file as a string slice, or [`None`] if the file has
+    /// no date information.
+    pub fn datestamp(&self) -> Option<&str> {
+        self.datestamp.as_deref()
+    }
+
+    /// Retrieve the time at which the Survex file was produced, if known.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// Retrieve the version of the Survex file format.
+    pub fn format_version(&self) -> i32 {
+        self.format_version
+    }
+
+    /// Retrieve the character which separates the components of station labels.
+    pub fn separator(&self) -> char {
+        self.separator
+    }
+
+    /// Retrieve whether the Survex file is an extended elevation. See
+    /// [`extended_elevation`][`SurveyData::extended_elevation`].
+    pub fn is_extended_elevation(&self) -> bool {
+        self.extended_elevation
+    }
+
+    /// Helper method to log a warning when a method which relies on the distances between
+    /// stations is called on an extended elevation, as the results will not be meaningful.
+    fn warn_if_extended_elevation(&self, method: &str) {
+        if self.extended_elevation {
+            warn!(
+                "{} called on an extended elevation, distances will not be accurate.",
+                method
+            );
         }
     }
 
+    /// Iterate over the stations in the survey, borrowing each in turn. This avoids having to
+    /// borrow each [`RefStation`] manually:
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use survex_rs::read::load_from_path;
+    ///
+    /// let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+    /// for station in data.iter_stations().take(5) {
+    ///     println!("{}", station.label);
+    /// }
+    /// ```
+    ///
+    /// Each station remains borrowed until the [`Ref`] returned for it is dropped, so stations
+    /// cannot be modified while they are held.
+    pub fn iter_stations(&self) -> impl Iterator<Item = Ref<'_, Station>> {
+        self.stations.iter().map(|station| station.borrow())
+    }
+
+    /// Iterate over the labels of the stations in the survey.
+    pub fn labels(&self) -> impl Iterator<Item = String> + '_ {
+        self.stations
+            .iter()
+            .map(|station| station.borrow().label.clone())
+    }
+
     /// Retrieve a reference to a [`Station`] by its label. Only exact matches are returned. To
     /// retrieve a station by partial label use
     /// [`get_by_label_part`][`SurveyData::get_by_label_part`].
+    ///
+    /// Lookups use an index of labels which is maintained by the methods of [`SurveyData`], so
+    /// this is a constant time operation. Stations must be relabelled using
+    /// [`relabel`][`SurveyData::relabel`] rather than by changing their label directly, or they
+    /// will not be found by their new label.
     pub fn get_by_label(&self, label: &str) -> Option<RefStation> {
-        for station in &self.stations {
-            if station.borrow().label == label {
+        let index = self.label_index.get(label)?;
+        if let Some(station) = self.stations.get(index.index()) {
+            let found = station.borrow();
+            if found.index == *index && found.label == label {
                 return Some(Rc::clone(station));
             }
         }
-        None
+
+        // The stations vector has been modified outside of the methods which maintain the index,
+        // so fall back to scanning it.
+        self.stations
+            .iter()
+            .find(|station| station.borrow().label == label)
+            .map(Rc::clone)
+    }
+
+    /// Change the label of the [`Station`] with the given index in the graph, updating the graph
+    /// and the label index used by [`get_by_label`][`SurveyData::get_by_label`]. Does nothing if
+    /// there is no station with the given index.
+    pub fn relabel(&mut self, index: NodeIndex, label: &str) {
+        let Some(station) = self.get_by_index(index) else {
+            return;
+        };
+        let old = std::mem::replace(&mut station.borrow_mut().label, String::from(label));
+        if self.label_index.get(&old) == Some(&index) {
+            self.label_index.remove(&old);
+        }
+        self.label_index.entry(String::from(label)).or_insert(index);
+        if let Some(weight) = self.graph.node_weight_mut(index) {
+            *weight = String::from(label);
+        }
     }
 
     /// Retrieve a reference to a [`Station`] by its label, allowing for partial matches. If
     /// multiple stations match the given label, [`None`] is returned, unless one of the matches is
     /// an exact match, in which case that station is returned.
+    ///
+    /// Use [`find_by_label_part`][`SurveyData::find_by_label_part`] to distinguish between no
+    /// matches and multiple matches.
     pub fn get_by_label_part(&self, label: &str) -> Option<RefStation> {
-        let matches = self
+        match self.find_by_label_part(label) {
+            LabelMatch::Exact(station) | LabelMatch::Unique(station) => Some(station),
+            LabelMatch::None | LabelMatch::Ambiguous(_) => None,
+        }
+    }
+
+    /// Search for a [`Station`] whose label contains the given label, reporting how the search
+    /// matched. An exact match is preferred over any number of partial matches. Otherwise, a
+    /// single partial match is returned as [`LabelMatch::Unique`], and multiple partial matches
+    /// are all returned as [`LabelMatch::Ambiguous`] so that the caller can suggest them.
+    pub fn find_by_label_part(&self, label: &str) -> LabelMatch {
+        let mut matches = self
             .stations
             .iter()
             .filter(|&node| node.borrow().label.contains(label))
+            .map(Rc::clone)
             .collect::<Vec<_>>();
 
-        if matches.len() == 1 {
-            return Some(Rc::clone(matches[0]));
-        } else {
-            for station in matches.iter() {
-                if station.borrow().label == label {
-                    return Some(Rc::clone(station));
-                }
-            }
+        if let Some(station) = matches
+            .iter()
+            .find(|station| station.borrow().label == label)
+        {
+            return LabelMatch::Exact(Rc::clone(station));
         }
 
-        // We have ruled out an exact match, so there is either no match or multiple matches, so
-        // just return None and hope the user can be more specific.
-        None
+        match matches.len() {
+            0 => LabelMatch::None,
+            1 => LabelMatch::Unique(matches.remove(0)),
+            _ => LabelMatch::Ambiguous(matches),
+        }
+    }
+
+    /// Search for stations whose labels are similar to `query`, allowing for typing mistakes, and
+    /// return up to `limit` of the best matches along with their scores. Scores range from 0 to 1,
+    /// where 1 is an exact match, and the results are sorted with the highest score first.
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use survex_rs::read::load_from_path;
+    ///
+    /// let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+    /// let matches = data.search_labels("nottsii.entrence", 5);
+    /// assert_eq!(matches[0].0.borrow().label, "nottsii.entrance");
+    /// ```
+    ///
+    /// Labels are compared without regard to case using the Jaro-Winkler similarity. The query is
+    /// compared both with the whole label and with each trailing part of it, such as `entrance`
+    /// and `nottsii.entrance` for `nottsii.entrance`, so that the survey prefix does not need to
+    /// be typed. Anonymous stations are never matched. Use
+    /// [`find_by_label_part`][`SurveyData::find_by_label_part`] for exact substring matches.
+    pub fn search_labels(&self, query: &str, limit: usize) -> Vec<(RefStation, f64)> {
+        let query = query.to_lowercase();
+        let mut matches = self
+            .stations
+            .iter()
+            .filter(|station| !station.borrow().anonymous())
+            .map(|station| {
+                let label = station.borrow().label.to_lowercase();
+                let score = label
+                    .match_indices(self.separator)
+                    .map(|(i, _)| &label[i + self.separator.len_utf8()..])
+                    .chain(std::iter::once(label.as_str()))
+                    .map(|part| fuzzy::jaro_winkler(&query, part))
+                    .fold(0.0, f64::max);
+                (Rc::clone(station), score)
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        matches.truncate(limit);
+        matches
     }
 
     /// Retrieve a reference to a [`Station`] by its coordinates. If multiple stations exist at the
@@ -82,14 +595,103 @@ impl SurveyData {
         None
     }
 
+    /// Retrieve a reference to a [`Station`] whose Euclidean distance from the given coordinates
+    /// is no more than `epsilon`. If multiple stations are within `epsilon` of the coordinates, the
+    /// first station found is returned.
+    ///
+    /// Unlike [`get_by_coords`][`SurveyData::get_by_coords`], this will match coordinates which
+    /// differ slightly due to floating point rounding.
+    pub fn get_by_coords_tol(&self, coords: &Point, epsilon: f64) -> Option<RefStation> {
+        self.warn_if_extended_elevation("get_by_coords_tol");
+        for station in &self.stations {
+            if station.borrow().coords.distance(coords) <= epsilon {
+                return Some(Rc::clone(station));
+            }
+        }
+        None
+    }
+
+    /// Retrieve a reference to the [`Station`] nearest to the given coordinates, or [`None`] if
+    /// there are no stations. If several stations are equally near, any one of them may be
+    /// returned.
+    ///
+    /// This uses a spatial index which is built the first time a spatial query is made, so the
+    /// first call is slower than subsequent calls. The index is rebuilt if stations are added or
+    /// moved using the methods of [`SurveyData`].
+    pub fn nearest_station(&self, p: &Point) -> Option<RefStation> {
+        self.warn_if_extended_elevation("nearest_station");
+        let position = self.with_spatial_index(|index| index.nearest(p))?;
+        self.stations.get(position).map(Rc::clone)
+    }
+
+    /// Retrieve the leg nearest to the given coordinates, such as for attaching a point surveyed
+    /// by other means to the survey. The leg is returned as a tuple of the two stations it
+    /// connects and the shortest distance from the point to the straight line between them.
+    /// Returns [`None`] if there are no legs.
+    pub fn nearest_leg(&self, p: &Point) -> Option<(RefStation, RefStation, f64)> {
+        self.warn_if_extended_elevation("nearest_leg");
+        self.graph
+            .edge_references()
+            .filter_map(|edge| {
+                let from = self.get_by_index(edge.source())?;
+                let to = self.get_by_index(edge.target())?;
+                let distance = p.distance_to_segment(&from.borrow().coords, &to.borrow().coords);
+                Some((from, to, distance))
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+    }
+
+    /// Retrieve references to all stations within `radius` metres of the given coordinates,
+    /// ordered by their position in the stations vector. See
+    /// [`nearest_station`][`SurveyData::nearest_station`] for details of the spatial index.
+    pub fn stations_within(&self, p: &Point, radius: f64) -> Vec<RefStation> {
+        self.warn_if_extended_elevation("stations_within");
+        let mut positions = self.with_spatial_index(|index| index.within(p, radius));
+        positions.sort();
+        positions
+            .into_iter()
+            .filter_map(|position| self.stations.get(position).map(Rc::clone))
+            .collect()
+    }
+
+    /// Helper method to run a query against the spatial index, building it first if it has not
+    /// yet been built or no longer matches the stations vector.
+    fn with_spatial_index<R>(&self, query: impl FnOnce(&KdTree) -> R) -> R {
+        let mut spatial_index = self.spatial_index.borrow_mut();
+        if spatial_index
+            .as_ref()
+            .is_none_or(|index| index.len() != self.stations.len())
+        {
+            let points = self
+                .stations
+                .iter()
+                .enumerate()
+                .map(|(position, station)| (station.borrow().coords, position))
+                .collect();
+            *spatial_index = Some(KdTree::new(points));
+        }
+        query(
+            spatial_index
+                .as_ref()
+                .expect("Spatial index was just built"),
+        )
+    }
+
     /// Retrieve a reference to a [`Station`] by its index in the graph.
+    ///
+    /// Stations added using the methods of [`SurveyData`] are stored in the stations vector at
+    /// the position given by their index, so this is a constant time operation. If the stations
+    /// vector has been reordered directly, this falls back to searching every station.
     pub fn get_by_index(&self, index: NodeIndex) -> Option<RefStation> {
-        for station in &self.stations {
+        if let Some(station) = self.stations.get(index.index()) {
             if station.borrow().index == index {
                 return Some(Rc::clone(station));
             }
         }
-        None
+        self.stations
+            .iter()
+            .find(|station| station.borrow().index == index)
+            .map(Rc::clone)
     }
 
     /// This helper method is used to add or update a [`Station`] to both the stations vector and
@@ -105,14 +707,3175 @@ impl SurveyData {
             let station_clone = Rc::clone(&station);
             let mut station_mut = station.borrow_mut();
             station_mut.coords = coords;
+            self.invalidate_caches();
             return (station_clone, index);
         }
 
-        let index = self.graph.add_node(String::from(label));
-        let station = Station::new(String::from(label), coords, index);
+        self.add(coords, label)
+    }
+
+    /// This helper method is used to add a [`Station`] to both the stations vector and the graph,
+    /// without merging it with any existing station of the same label.
+    ///
+    /// If any [`Station`] with the given label already exists at the same coordinates, that
+    /// station is returned. If stations with the label exist only at different coordinates, the
+    /// conflict with the first of them is recorded in
+    /// [`label_conflicts`][`SurveyData::label_conflicts`] and a new, distinct [`Station`] is
+    /// added with the same label. In either case, a reference to the station is returned in a
+    /// tuple along with the index of the station in the graph.
+    pub fn add_or_record_conflict(
+        &mut self,
+        coords: Point,
+        label: &str,
+    ) -> (RefStation, NodeIndex) {
+        if let Some(station) = self.get_by_label(label) {
+            let existing = station.borrow().coords;
+            if existing == coords {
+                let index = station.borrow().index;
+                return (station, index);
+            }
+
+            // The label index only refers to the first station with each label, so any later
+            // stations which share it have to be found by searching.
+            let duplicate = self.stations.iter().find(|station| {
+                let station = station.borrow();
+                station.label == label && station.coords == coords
+            });
+            if let Some(station) = duplicate {
+                let station = Rc::clone(station);
+                let index = station.borrow().index;
+                return (station, index);
+            }
+            self.label_conflicts
+                .push((String::from(label), existing, coords));
+        }
+
+        self.add(coords, label)
+    }
+
+    /// Add a new [`Station`] to the stations vector and the graph.
+    fn add(&mut self, coords: Point, label: &str) -> (RefStation, NodeIndex) {
+        let station = Station::new(String::from(label), coords, NodeIndex::end());
+        self.push_station(station)
+    }
+
+    /// Add the given [`Station`] to the stations vector and the graph, updating its index to
+    /// match its new position in the graph and adding it to the label index. If another station
+    /// already has the same label, the label index continues to refer to the existing station.
+    pub(crate) fn push_station(&mut self, mut station: Station) -> (RefStation, NodeIndex) {
+        let index = self.graph.add_node(station.label.clone());
+        station.index = index;
+        self.label_index
+            .entry(station.label.clone())
+            .or_insert(index);
         let ref_station = Rc::new(RefCell::new(station));
-        let station_clone = Rc::clone(&ref_station);
-        self.stations.push(ref_station);
-        (station_clone, index)
+        self.stations.push(Rc::clone(&ref_station));
+        self.invalidate_caches();
+        (ref_station, index)
+    }
+
+    /// Discard the spatial index and any cached values, so that they are rebuilt from the current
+    /// stations and legs when next needed.
+    fn invalidate_caches(&mut self) {
+        self.spatial_index.get_mut().take();
+        self.length_cache.get_mut().take();
+        self.bounding_box_cache.get_mut().take();
+    }
+
+    /// Merge another [`SurveyData`] instance into this one, so that they can be analysed together
+    /// on a single graph.
+    ///
+    /// Stations from `other` are appended with new indices, and all legs from `other` are added
+    /// to the graph between the corresponding stations. Stations which share a label are handled
+    /// according to `collisions`: with [`LabelCollision::Weld`], the existing station is kept
+    /// as-is and legs from `other` are connected to it, while [`LabelCollision::Prefix`] relabels
+    /// the stations from `other` so that they remain distinct.
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use survex_rs::data::LabelCollision;
+    /// use survex_rs::read::load_from_path;
+    ///
+    /// let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+    /// let other = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+    /// data.merge(other, LabelCollision::Prefix(String::from("0733")));
+    /// assert!(data.get_by_label("nottsii.entrance").is_some());
+    /// ```
+    pub fn merge(&mut self, other: SurveyData, collisions: LabelCollision) {
+        let mut remap = HashMap::new();
+        for station in &other.stations {
+            let mut station = station.borrow().clone();
+            if let LabelCollision::Prefix(prefix) = &collisions {
+                if !station.anonymous() {
+                    station.label = format!("{}{}{}", prefix, self.separator, station.label);
+                }
+            }
+            let index = match self.get_by_label(&station.label) {
+                Some(existing) => existing.borrow().index,
+                None => self.push_station(station.clone()).1,
+            };
+            remap.insert(station.index, index);
+        }
+
+        for edge in other.graph.edge_references() {
+            if let (Some(&a), Some(&b)) = (remap.get(&edge.source()), remap.get(&edge.target())) {
+                self.graph.add_edge(a, b, *edge.weight());
+            }
+        }
+
+        self.label_conflicts.extend(other.label_conflicts);
+        self.loop_errors.extend(other.loop_errors);
+        self.invalidate_caches();
+    }
+
+    /// Remove every [`Station`] for which `pred` returns `false`, along with every leg connected
+    /// to a removed station, such as to drop surface stations before analysis:
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use survex_rs::read::load_from_path;
+    ///
+    /// let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+    /// data.retain_stations(|station| !station.surface());
+    /// assert!(data.iter_stations().all(|station| !station.surface()));
+    /// ```
+    ///
+    /// The graph is rebuilt without the removed stations, so the remaining stations are given new
+    /// indices in the same relative order as before. Existing references to the remaining
+    /// stations stay valid and reflect their new indices, while references to removed stations
+    /// are no longer part of the survey.
+    pub fn retain_stations(&mut self, pred: impl Fn(&Station) -> bool) {
+        let mut graph = StationGraph::new_undirected();
+        let mut remap = HashMap::new();
+        self.label_index.clear();
+        for station in std::mem::take(&mut self.stations) {
+            if !pred(&station.borrow()) {
+                continue;
+            }
+            let label = station.borrow().label.clone();
+            let index = graph.add_node(label.clone());
+            let old = std::mem::replace(&mut station.borrow_mut().index, index);
+            remap.insert(old, index);
+            self.label_index.entry(label).or_insert(index);
+            self.stations.push(station);
+        }
+
+        for edge in self.graph.edge_references() {
+            if let (Some(&a), Some(&b)) = (remap.get(&edge.source()), remap.get(&edge.target())) {
+                graph.add_edge(a, b, *edge.weight());
+            }
+        }
+        self.graph = graph;
+        self.invalidate_caches();
+    }
+
+    /// Retrieve the loop closure error statistics of each traverse. See [`LoopError`] for a
+    /// description of the values.
+    pub fn loop_errors(&self) -> &[LoopError] {
+        &self.loop_errors
+    }
+
+    /// Retrieve the traverse with the largest horizontal loop closure error, or [`None`] if the
+    /// Survex file contains no loop closure information.
+    pub fn worst_loop_by_horizontal_error(&self) -> Option<&LoopError> {
+        self.loop_errors
+            .iter()
+            .max_by(|a, b| a.horizontal_error.total_cmp(&b.horizontal_error))
+    }
+
+    /// Retrieve the `n` legs with the largest loop closure error, in descending order of error.
+    /// Each leg is returned as a tuple of the two stations it connects and its error. Legs with
+    /// no error information are not included, so fewer than `n` legs may be returned.
+    ///
+    /// See [`Leg::error`] for a description of the error value.
+    pub fn worst_legs_by_error(&self, n: usize) -> Vec<(RefStation, RefStation, f64)> {
+        let mut legs = self
+            .graph
+            .edge_references()
+            .filter_map(|edge| edge.weight().error.map(|error| (edge, error)))
+            .collect::<Vec<_>>();
+        legs.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        legs.into_iter()
+            .take(n)
+            .filter_map(|(edge, error)| {
+                let from = self.get_by_index(edge.source())?;
+                let to = self.get_by_index(edge.target())?;
+                Some((from, to, error))
+            })
+            .collect()
+    }
+
+    /// Calculate the total length of legs which are present in this survey but absent from the
+    /// given `baseline` survey, such as the length of new passage surveyed since the baseline was
+    /// produced.
+    ///
+    /// Legs are matched by the coordinates of their endpoints, in either direction, rather than by
+    /// station label. Two endpoints are considered equal if they are within `tolerance` metres of
+    /// each other on every axis. A `tolerance` of zero, or any negative value, requires the
+    /// endpoints to match exactly.
+    pub fn new_length_vs(&self, baseline: &SurveyData, tolerance: f64) -> f64 {
+        let tolerance = tolerance.max(0.0);
+
+        // Bucket the baseline legs into a grid keyed by the cell of each of their endpoints, so
+        // that each leg in this survey only needs to be compared against nearby baseline legs.
+        // The cells are at least a millimetre across so that real coordinates divided by the cell
+        // size stay well within the range of an i64.
+        let cell_size = (tolerance * 2.0).max(0.001);
+        let cell = |p: &Point| {
+            (
+                (p.x / cell_size).floor() as i64,
+                (p.y / cell_size).floor() as i64,
+                (p.z / cell_size).floor() as i64,
+            )
+        };
+
+        let baseline_legs = baseline.leg_coords();
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for (i, (p1, p2, _)) in baseline_legs.iter().enumerate() {
+            grid.entry(cell(p1)).or_default().push(i);
+            grid.entry(cell(p2)).or_default().push(i);
+        }
+
+        let close = |a: &Point, b: &Point| {
+            (a.x - b.x).abs() <= tolerance
+                && (a.y - b.y).abs() <= tolerance
+                && (a.z - b.z).abs() <= tolerance
+        };
+
+        let mut length = 0.0;
+        for (p1, p2, leg_length) in self.leg_coords() {
+            let (cx, cy, cz) = cell(&p1);
+            let mut found = false;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let neighbour = (
+                            cx.saturating_add(dx),
+                            cy.saturating_add(dy),
+                            cz.saturating_add(dz),
+                        );
+                        let Some(candidates) = grid.get(&neighbour) else {
+                            continue;
+                        };
+                        for &i in candidates {
+                            let (b1, b2, _) = &baseline_legs[i];
+                            if (close(&p1, b1) && close(&p2, b2))
+                                || (close(&p1, b2) && close(&p2, b1))
+                            {
+                                found = true;
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !found {
+                length += leg_length;
+            }
+        }
+        length
+    }
+
+    /// Calculate the total length of all legs in metres, optionally including surface legs and
+    /// duplicate legs. Splay legs are always included.
+    ///
+    /// The totals are calculated on the first call and cached, so subsequent calls take constant
+    /// time. The cache is discarded when legs are added or reweighted by the methods of this
+    /// struct, or when the number of legs in the graph changes. If leg lengths are changed
+    /// directly through the [`graph`][`SurveyData::graph`], the cached totals will be out of
+    /// date.
+    pub fn total_length(&self, include_surface: bool, include_duplicate: bool) -> f64 {
+        self.warn_if_extended_elevation("total_length");
+        let legs = self.graph.edge_count();
+        let mut cache = self.length_cache.borrow_mut();
+        let totals = match *cache {
+            Some(cached) if cached.count == legs => cached.value,
+            _ => {
+                let mut totals = [0.0; 4];
+                for leg in self.graph.edge_weights() {
+                    for (i, total) in totals.iter_mut().enumerate() {
+                        let (surface, duplicate) = (i & 2 != 0, i & 1 != 0);
+                        if (surface || !leg.flags.surface) && (duplicate || !leg.flags.duplicate) {
+                            *total += leg.length;
+                        }
+                    }
+                }
+                *cache = Some(Cached {
+                    count: legs,
+                    value: totals,
+                });
+                totals
+            }
+        };
+        totals[usize::from(include_surface) * 2 + usize::from(include_duplicate)]
+    }
+
+    /// Calculate the total length of all underground legs in metres, excluding surface legs.
+    /// Duplicate and splay legs are included.
+    pub fn total_length_underground(&self) -> f64 {
+        self.total_length(false, true)
+    }
+
+    /// Estimate the volume of the passages in the survey in cubic metres, using the LRUD
+    /// measurements of each station.
+    ///
+    /// Each leg is treated as a prism whose cross-sectional area is the average of the
+    /// [areas][`crate::station::LRUD::area`] at its two stations, so its volume is that average
+    /// multiplied by the length of the leg. Legs are skipped if either station is missing any
+    /// LRUD measurement, as are splay and duplicate legs, which do not represent additional
+    /// passage. Overlap between legs at junctions is not accounted for. LRUD measurements are
+    /// converted to the [units][`SurveyData::units`] of the survey coordinates first.
+    pub fn passage_volume(&self) -> f64 {
+        self.warn_if_extended_elevation("passage_volume");
+        self.graph
+            .edge_references()
+            .filter(|edge| !edge.weight().flags.splay && !edge.weight().flags.duplicate)
+            .filter_map(|edge| {
+                let from = self
+                    .get_by_index(edge.source())?
+                    .borrow()
+                    .lrud
+                    .convert(self.units)
+                    .area()?;
+                let to = self
+                    .get_by_index(edge.target())?
+                    .borrow()
+                    .lrud
+                    .convert(self.units)
+                    .area()?;
+                Some((from + to) / 2.0 * edge.weight().length)
+            })
+            .sum()
+    }
+
+    /// Reconstruct the walls of the passages in the survey from the LRUD measurements of each
+    /// station, such as for drawing passage outlines in plan or section.
+    ///
+    /// A [`PassagePolygon`] is returned for every leg which has all four LRUD measurements at
+    /// both of its stations, in the order the legs are stored in the graph. Splay legs are
+    /// skipped, as they do not follow the passage. The cross-section at each station is vertical
+    /// and at right angles to the leg in plan: the left and right walls are offset from the
+    /// station horizontally by the left and right measurements, along the unit vector
+    /// `(dy, -dx, 0) / sqrt(dx² + dy²)` for a leg from the first station to the second of
+    /// `(dx, dy, dz)`, and the roof and floor are offset vertically by the up and down
+    /// measurements. Vertical legs use the x axis for left and right. LRUD measurements are
+    /// converted to the [units][`SurveyData::units`] of the survey coordinates first.
+    pub fn passage_walls(&self) -> Vec<PassagePolygon> {
+        let measurements = |station: &Station| {
+            let lrud = station.lrud.convert(self.units);
+            Some([lrud.left?, lrud.right?, lrud.up?, lrud.down?])
+        };
+        self.graph
+            .edge_references()
+            .filter(|edge| !edge.weight().flags.splay)
+            .filter_map(|edge| {
+                let from = self.get_by_index(edge.source())?;
+                let to = self.get_by_index(edge.target())?;
+                let (from, to) = (from.borrow(), to.borrow());
+                let (from_lrud, to_lrud) = (measurements(&from)?, measurements(&to)?);
+                let direction = to.coords - from.coords;
+                Some(PassagePolygon {
+                    from: from.index,
+                    to: to.index,
+                    start: cross_section(from.coords, direction, from_lrud),
+                    end: cross_section(to.coords, direction, to_lrud),
+                })
+            })
+            .collect()
+    }
+
+    /// Calculate a histogram of leg lengths. Legs are counted into bins of `bin_width` metres,
+    /// starting from zero, and the result contains a tuple of the lower bound of each bin along
+    /// with the number of legs in that bin. Every bin up to and including the one containing the
+    /// longest leg is returned, even if it is empty.
+    ///
+    /// An empty vector is returned if there are no legs, `bin_width` is not a positive finite
+    /// number, or the longest leg would need more than [`MAX_BINS`] bins.
+    pub fn length_histogram(&self, bin_width: f64) -> Vec<(f64, usize)> {
+        if !(bin_width.is_finite() && bin_width > 0.0) || self.graph.edge_count() == 0 {
+            return Vec::new();
+        }
+
+        let longest = self
+            .graph
+            .edge_weights()
+            .map(|leg| leg.length)
+            .fold(0.0, f64::max);
+        if longest / bin_width >= MAX_BINS as f64 {
+            return Vec::new();
+        }
+
+        let mut counts = vec![0; (longest / bin_width).floor() as usize + 1];
+        for leg in self.graph.edge_weights() {
+            counts[(leg.length / bin_width).floor() as usize] += 1;
+        }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(bin, count)| (bin as f64 * bin_width, count))
+            .collect()
+    }
+
+    /// Calculate the total length of legs heading in each direction, for drawing a rose diagram
+    /// of passage bearings. The compass is divided into `bins` equal sectors, starting with the
+    /// sector clockwise from north, and the result contains the total length of the legs whose
+    /// [bearing][`Point::bearing`] falls in each sector.
+    ///
+    /// The bearing of a leg is taken from its first station to its second, in the order they are
+    /// stored in the graph. Vertical legs have no bearing and are excluded, as are splay legs,
+    /// which do not follow the passage. An empty vector is returned if `bins` is zero.
+    pub fn bearing_histogram(&self, bins: usize) -> Vec<f64> {
+        let mut totals = vec![0.0; bins];
+        if bins == 0 {
+            return totals;
+        }
+
+        let sector = 360.0 / bins as f64;
+        for edge in self.graph.edge_references() {
+            if edge.weight().flags.splay {
+                continue;
+            }
+            let (Some(from), Some(to)) = (
+                self.get_by_index(edge.source()),
+                self.get_by_index(edge.target()),
+            ) else {
+                continue;
+            };
+            let (from, to) = (from.borrow().coords, to.borrow().coords);
+            if from.horizontal_distance(&to) == 0.0 {
+                continue;
+            }
+            // The bearing may round up to exactly 360 degrees, which belongs in the first sector.
+            let bin = (from.bearing(&to) / sector).floor() as usize % bins;
+            totals[bin] += edge.weight().length;
+        }
+        totals
+    }
+
+    /// Calculate the local trend of the passage at the station with the given label, such as for
+    /// orienting a cross-section drawn at that station.
+    ///
+    /// The trend is the average bearing of the legs meeting the station, in degrees clockwise
+    /// from north (+y). A leg arriving at a station in the middle of a passage points the
+    /// opposite way to the leg leaving it, so bearings are averaged as axes rather than
+    /// directions: each bearing is doubled, the doubled bearings are averaged as unit vectors
+    /// weighted by the horizontal length of each leg, and the resulting angle is halved. As the
+    /// trend has no direction, it is in the range `0.0..180.0`.
+    ///
+    /// Splay legs are ignored, as they do not follow the passage. Returns [`None`] if no station
+    /// has the given label, if the station has no other legs, if all of its legs are vertical,
+    /// or if its legs are evenly spread so that there is no single trend, such as at a
+    /// crossroads of equal legs.
+    pub fn station_trend(&self, label: &str) -> Option<f64> {
+        let legs = self.station_legs(label)?;
+        let (mut sum_x, mut sum_y) = (0.0, 0.0);
+        for leg in &legs {
+            let length = leg.x.hypot(leg.y);
+            let angle = 2.0 * leg.x.atan2(leg.y);
+            sum_x += length * angle.sin();
+            sum_y += length * angle.cos();
+        }
+        if sum_x.hypot(sum_y) < 1e-9 {
+            return None;
+        }
+        Some((0.5 * sum_x.atan2(sum_y).to_degrees()).rem_euclid(180.0))
+    }
+
+    /// Calculate the local gradient of the passage at the station with the given label, in
+    /// degrees from horizontal in the range -90 to 90.
+    ///
+    /// Each leg meeting the station is oriented to point along its [trend][`Self::station_trend`],
+    /// and the gradient is that of the sum of the oriented legs, so longer legs carry more
+    /// weight. The gradient is positive if the passage rises when heading along the trend
+    /// bearing. If the station has no trend, such as at the head of a vertical shaft, the legs
+    /// can not be oriented and the average of the absolute gradients of the legs, weighted by
+    /// their length, is returned instead.
+    ///
+    /// Splay legs are ignored. Returns [`None`] if no station has the given label or if the
+    /// station has no other legs.
+    pub fn station_gradient(&self, label: &str) -> Option<f64> {
+        let legs = self.station_legs(label)?;
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let Some(trend) = self.station_trend(label) else {
+            let (total, weighted) = legs.iter().fold((0.0, 0.0), |(total, weighted), leg| {
+                let length = origin.distance(leg);
+                (
+                    total + length,
+                    weighted + length * origin.gradient(leg).abs(),
+                )
+            });
+            return Some(weighted / total);
+        };
+
+        let (east, north) = trend.to_radians().sin_cos();
+        let sum = legs.iter().fold(origin, |sum, leg| {
+            if leg.x * east + leg.y * north < 0.0 {
+                sum - *leg
+            } else {
+                sum + *leg
+            }
+        });
+        Some(origin.gradient(&sum))
+    }
+
+    /// Retrieve the vector from the station with the given label to each station it shares a
+    /// non-splay leg with, skipping legs of zero length. Returns [`None`] if no station has the
+    /// given label or if there are no such legs.
+    fn station_legs(&self, label: &str) -> Option<Vec<Point>> {
+        let station = self.get_by_label(label)?;
+        let (index, coords) = {
+            let station = station.borrow();
+            (station.index, station.coords)
+        };
+        let legs = self
+            .graph
+            .edges(index)
+            .filter(|edge| !edge.weight().flags.splay)
+            .filter_map(|edge| {
+                let other = if edge.source() == index {
+                    edge.target()
+                } else {
+                    edge.source()
+                };
+                let other = self.get_by_index(other)?.borrow().coords;
+                Some(other - coords)
+            })
+            .filter(|leg| leg.x != 0.0 || leg.y != 0.0 || leg.z != 0.0)
+            .collect::<Vec<_>>();
+        (!legs.is_empty()).then_some(legs)
+    }
+
+    /// Estimate the depth of each underground station below the surface, approximating the
+    /// thickness of rock above it.
+    ///
+    /// For every station flagged as underground, the surface station which is nearest to it in
+    /// plan (i.e. with the smallest horizontal distance, ignoring z) is found, and the depth is
+    /// the z coordinate of that surface station minus the z coordinate of the underground station.
+    /// The result is keyed by the index of the underground station in the graph. A negative depth
+    /// indicates that the nearest surface station is lower than the underground station.
+    ///
+    /// If the survey contains no surface stations, an empty map is returned.
+    pub fn depth_below_surface(&self) -> HashMap<NodeIndex, f64> {
+        // The surface stations are flattened onto the plane z = 0 so that the nearest point in the
+        // tree to a flattened underground station is the nearest in plan.
+        let plan = |p: &Point| Point::new(p.x, p.y, 0.0);
+        let surface = self
+            .iter_stations()
+            .filter(|station| station.surface())
+            .map(|station| station.coords.z)
+            .collect::<Vec<_>>();
+        let tree = KdTree::new(
+            self.iter_stations()
+                .filter(|station| station.surface())
+                .map(|station| plan(&station.coords))
+                .zip(0..)
+                .collect(),
+        );
+
+        let mut depths = HashMap::new();
+        for station in self.iter_stations() {
+            if !station.underground() {
+                continue;
+            }
+            let Some(nearest) = tree.nearest(&plan(&station.coords)) else {
+                break;
+            };
+            depths.insert(station.index, surface[nearest] - station.coords.z);
+        }
+        depths
+    }
+
+    /// Calculate how far the station with the given label is below the nearest entrance, as
+    /// cavers usually quote depths. The nearest entrance is the station flagged as an entrance
+    /// with the smallest straight line distance to the station, and the depth is its z coordinate
+    /// minus the z coordinate of the station, so stations above the entrance have a negative
+    /// depth.
+    ///
+    /// Returns [`None`] if the label does not match a station or the survey has no entrances. Use
+    /// [`depth_below_entrance_from`][`SurveyData::depth_below_entrance_from`] to measure from a
+    /// particular entrance.
+    pub fn depth_below_entrance(&self, label: &str) -> Option<f64> {
+        let station = self.get_by_label(label)?;
+        let coords = station.borrow().coords;
+        let entrance = self
+            .stations
+            .iter()
+            .map(|station| station.borrow())
+            .filter(|station| station.entrance())
+            .map(|station| station.coords)
+            .min_by(|a, b| a.distance(&coords).total_cmp(&b.distance(&coords)))?;
+        Some(entrance.z - coords.z)
+    }
+
+    /// Calculate how far the station with the given label is below the station labelled
+    /// `entrance`, which need not be flagged as an entrance. See
+    /// [`depth_below_entrance`][`SurveyData::depth_below_entrance`].
+    ///
+    /// Returns [`None`] if either label does not match a station.
+    pub fn depth_below_entrance_from(&self, label: &str, entrance: &str) -> Option<f64> {
+        let station = self.get_by_label(label)?.borrow().coords;
+        let entrance = self.get_by_label(entrance)?.borrow().coords;
+        Some(entrance.z - station.z)
+    }
+
+    /// Calculate the vertical extent of the survey as a tuple of the lowest and highest z
+    /// coordinates of any station, or [`None`] if there are no stations.
+    pub fn vertical_range(&self) -> Option<(f64, f64)> {
+        let mut range: Option<(f64, f64)> = None;
+        for station in &self.stations {
+            let z = station.borrow().coords.z;
+            range = Some(match range {
+                Some((min, max)) => (min.min(z), max.max(z)),
+                None => (z, z),
+            });
+        }
+        range
+    }
+
+    /// Calculate the depth of the survey in metres, which is the difference between the highest
+    /// and lowest stations. Returns zero if there are no stations.
+    pub fn depth(&self) -> f64 {
+        self.vertical_range().map_or(0.0, |(min, max)| max - min)
+    }
+
+    /// Calculate the axis-aligned [`BoundingBox`] of all stations in the survey, or [`None`] if
+    /// there are no stations.
+    ///
+    /// As with [`total_length`][`SurveyData::total_length`], the result is cached after the first
+    /// call. The cache is discarded when stations are added or moved by the methods of this
+    /// struct, but not if the coordinates of a [`Station`] are changed directly.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut cache = self.bounding_box_cache.borrow_mut();
+        match *cache {
+            Some(cached) if cached.count == self.stations.len() => cached.value,
+            _ => {
+                let value = BoundingBox::from_points(
+                    self.stations.iter().map(|station| station.borrow().coords),
+                );
+                *cache = Some(Cached {
+                    count: self.stations.len(),
+                    value,
+                });
+                value
+            }
+        }
+    }
+
+    /// Find the two stations which are furthest apart in a straight line, which is the overall
+    /// extent of the survey, returning them along with the distance between them in metres.
+    /// Returns [`None`] if there are fewer than two stations.
+    ///
+    /// Every pair of stations is compared, so this takes time proportional to the square of the
+    /// number of stations. This could be reduced by only comparing the stations on the convex
+    /// hull of the survey, as the furthest pair of points always lies on the hull.
+    pub fn extent(&self) -> Option<(RefStation, RefStation, f64)> {
+        self.warn_if_extended_elevation("extent");
+        let coords = self
+            .stations
+            .iter()
+            .map(|station| station.borrow().coords)
+            .collect::<Vec<_>>();
+        let mut furthest: Option<(usize, usize, f64)> = None;
+        for (i, a) in coords.iter().enumerate() {
+            for (j, b) in coords.iter().enumerate().skip(i + 1) {
+                let distance = a.distance(b);
+                if furthest.is_none_or(|(_, _, max)| distance > max) {
+                    furthest = Some((i, j, distance));
+                }
+            }
+        }
+        let (i, j, distance) = furthest?;
+        Some((
+            Rc::clone(&self.stations[i]),
+            Rc::clone(&self.stations[j]),
+            distance,
+        ))
+    }
+
+    /// Check whether the [bounding boxes][`SurveyData::bounding_box`] of this survey and another
+    /// survey intersect. This is a cheap check of whether two surveys may need to be connected.
+    /// Surveys with no stations never overlap.
+    pub fn overlaps(&self, other: &SurveyData) -> bool {
+        match (self.bounding_box(), other.bounding_box()) {
+            (Some(a), Some(b)) => a.intersection(&b).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Calculate the volume, in cubic metres, of the space shared by the
+    /// [bounding boxes][`SurveyData::bounding_box`] of this survey and another survey. Returns
+    /// zero if they do not overlap.
+    pub fn spatial_overlap_volume(&self, other: &SurveyData) -> f64 {
+        match (self.bounding_box(), other.bounding_box()) {
+            (Some(a), Some(b)) => a.intersection(&b).map_or(0.0, |bbox| bbox.volume()),
+            _ => 0.0,
+        }
+    }
+
+    /// Group the stations into square tiles of `tile_size` metres in plan view, keyed by the tile
+    /// indices returned by [`Point::tile`].
+    pub fn stations_by_tile(&self, tile_size: f64) -> HashMap<(i64, i64), Vec<RefStation>> {
+        let mut tiles: HashMap<(i64, i64), Vec<RefStation>> = HashMap::new();
+        for station in &self.stations {
+            let tile = station.borrow().coords.tile(tile_size);
+            tiles.entry(tile).or_default().push(Rc::clone(station));
+        }
+        tiles
+    }
+
+    /// Calculate the cumulative length of survey legs as a function of depth, for plotting a
+    /// "length vs depth" chart.
+    ///
+    /// Depth is measured downwards from the highest station in the survey. Each leg's length is
+    /// assigned to the bin of `bin` metres containing the depth of its midpoint. The result
+    /// contains a tuple for each bin, from the surface downwards, of the depth at the bottom of
+    /// the bin and the total length of all legs at or above that depth.
+    ///
+    /// An empty vector is returned if there are no legs, `bin` is not a positive finite number,
+    /// or the deepest leg would need more than [`MAX_BINS`] bins.
+    pub fn length_depth_profile(&self, bin: f64) -> Vec<(f64, f64)> {
+        let legs = self.leg_coords();
+        if !(bin.is_finite() && bin > 0.0) || legs.is_empty() {
+            return Vec::new();
+        }
+
+        let top = self
+            .stations
+            .iter()
+            .map(|station| station.borrow().coords.z)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let legs = legs
+            .into_iter()
+            .map(|(p1, p2, length)| (top - (p1.z + p2.z) / 2.0, length))
+            .collect::<Vec<_>>();
+
+        let deepest = legs.iter().map(|(depth, _)| *depth).fold(0.0, f64::max);
+        if deepest / bin >= MAX_BINS as f64 {
+            return Vec::new();
+        }
+
+        let mut lengths = vec![0.0; (deepest / bin).floor() as usize + 1];
+        for (depth, length) in legs {
+            lengths[(depth / bin).floor() as usize] += length;
+        }
+
+        let mut cumulative = 0.0;
+        lengths
+            .into_iter()
+            .enumerate()
+            .map(|(index, length)| {
+                cumulative += length;
+                ((index + 1) as f64 * bin, cumulative)
+            })
+            .collect()
+    }
+
+    /// Retrieve all stations which have no legs connected to them. Such stations, such as a lone
+    /// fixed point, often indicate a problem with the imported data.
+    pub fn isolated_stations(&self) -> Vec<RefStation> {
+        self.stations
+            .iter()
+            .filter(|station| {
+                let index = station.borrow().index;
+                self.graph.edges(index).next().is_none()
+            })
+            .map(Rc::clone)
+            .collect()
+    }
+
+    /// Retrieve all orphan stations, which are stations with no legs connected to them. This is
+    /// the same as [`isolated_stations`][`Self::isolated_stations`].
+    pub fn orphan_stations(&self) -> Vec<RefStation> {
+        self.isolated_stations()
+    }
+
+    /// Check the survey for common signs of a problem with the imported data, such as for a quick
+    /// integrity check after loading a file. An empty vector is returned if no problems are found.
+    ///
+    /// The survey is checked for isolated (or orphan) stations, as returned by
+    /// [`isolated_stations`][`Self::isolated_stations`], stations with
+    /// NaN coordinates and stations with different labels at exactly the same coordinates. The
+    /// warnings are grouped in that order, and within each group are in the order of the
+    /// stations vector. Stations with NaN coordinates are not checked for duplicates.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut warnings = self
+            .isolated_stations()
+            .iter()
+            .map(|station| Warning::IsolatedStation(station.borrow().label.clone()))
+            .collect::<Vec<_>>();
+
+        let is_nan = |p: &Point| p.x.is_nan() || p.y.is_nan() || p.z.is_nan();
+        warnings.extend(
+            self.iter_stations()
+                .filter(|station| is_nan(&station.coords))
+                .map(|station| Warning::NanCoordinates(station.label.clone())),
+        );
+
+        let mut positions: HashMap<[u64; 3], Vec<String>> = HashMap::new();
+        let mut order = Vec::new();
+        for station in self.iter_stations() {
+            if is_nan(&station.coords) {
+                continue;
+            }
+            let key = coordinate_key(&station.coords);
+            let labels = positions.entry(key).or_default();
+            if labels.len() == 1 {
+                order.push(key);
+            }
+            labels.push(station.label.clone());
+        }
+        warnings.extend(
+            order
+                .iter()
+                .filter_map(|key| positions.remove(key))
+                .map(Warning::DuplicateCoordinates),
+        );
+        warnings
+    }
+
+    /// Retrieve all stations flagged as underground, in the order they appear in the stations
+    /// vector.
+    pub fn underground_stations(&self) -> Vec<RefStation> {
+        self.stations_where(Station::underground)
+    }
+
+    /// Retrieve all stations flagged as surface stations, in the order they appear in the
+    /// stations vector. A station can be both a surface and an underground station, such as an
+    /// entrance where surface and underground surveys meet.
+    pub fn surface_stations(&self) -> Vec<RefStation> {
+        self.stations_where(Station::surface)
+    }
+
+    /// Retrieve all stations flagged as entrances, in the order they appear in the stations
+    /// vector.
+    pub fn entrances(&self) -> Vec<RefStation> {
+        self.stations_where(Station::entrance)
+    }
+
+    /// Helper method to retrieve all stations which match the given predicate, in the order they
+    /// appear in the stations vector.
+    fn stations_where(&self, predicate: impl Fn(&Station) -> bool) -> Vec<RefStation> {
+        self.stations
+            .iter()
+            .filter(|station| predicate(&station.borrow()))
+            .map(Rc::clone)
+            .collect()
+    }
+
+    /// Calculate the dominant trend of the survey in plan view, such as for automatically
+    /// orienting an elevation view.
+    ///
+    /// This is the direction of the first principal component of the x and y coordinates of all
+    /// stations, given as a bearing in degrees clockwise from north (+y). As the axis has no
+    /// direction, the bearing is in the range `0.0..180.0`. Returns [`None`] if there are fewer
+    /// than two stations, or if all stations share the same x and y coordinates.
+    pub fn principal_axis(&self) -> Option<f64> {
+        if self.stations.len() < 2 {
+            return None;
+        }
+
+        let n = self.stations.len() as f64;
+        let (mut sum_x, mut sum_y) = (0.0, 0.0);
+        for station in &self.stations {
+            let coords = station.borrow().coords;
+            sum_x += coords.x;
+            sum_y += coords.y;
+        }
+        let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+
+        let (mut cxx, mut cyy, mut cxy) = (0.0, 0.0, 0.0);
+        for station in &self.stations {
+            let coords = station.borrow().coords;
+            let (dx, dy) = (coords.x - mean_x, coords.y - mean_y);
+            cxx += dx * dx;
+            cyy += dy * dy;
+            cxy += dx * dy;
+        }
+        if cxx + cyy == 0.0 {
+            return None;
+        }
+
+        // The angle of the major axis of the covariance matrix, anticlockwise from +x.
+        let theta = 0.5 * (2.0 * cxy).atan2(cxx - cyy);
+        let bearing = theta.cos().atan2(theta.sin()).to_degrees();
+        Some(bearing.rem_euclid(180.0))
+    }
+
+    /// Calculate a grid of survey density in the given [`Projection`], suitable for rendering as
+    /// a heatmap.
+    ///
+    /// The projected extent of the survey is divided into square cells of `cell_size` metres,
+    /// starting from the smallest projected coordinates of any station. The length of each leg is
+    /// added to the cell containing its projected midpoint. The grid is returned as a vector of
+    /// rows, ordered by increasing vertical coordinate, each containing the cells ordered by
+    /// increasing horizontal coordinate.
+    ///
+    /// An empty vector is returned if there are no stations or `cell_size` is not positive.
+    pub fn density_grid(&self, cell_size: f64, view: Projection) -> Vec<Vec<f64>> {
+        if cell_size <= 0.0 || self.stations.is_empty() {
+            return Vec::new();
+        }
+
+        let (mut min_u, mut min_v) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_u, mut max_v) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for station in &self.stations {
+            let (u, v) = station.borrow().coords.project(view);
+            (min_u, min_v) = (min_u.min(u), min_v.min(v));
+            (max_u, max_v) = (max_u.max(u), max_v.max(v));
+        }
+
+        let cell = |value: f64, min: f64| ((value - min) / cell_size).floor() as usize;
+        let (columns, rows) = (cell(max_u, min_u) + 1, cell(max_v, min_v) + 1);
+        let mut grid = vec![vec![0.0; columns]; rows];
+
+        for (p1, p2, length) in self.leg_coords() {
+            let midpoint = Point::new(
+                (p1.x + p2.x) / 2.0,
+                (p1.y + p2.y) / 2.0,
+                (p1.z + p2.z) / 2.0,
+            );
+            let (u, v) = midpoint.project(view);
+            let row = cell(v, min_v).min(rows - 1);
+            let column = cell(u, min_u).min(columns - 1);
+            grid[row][column] += length;
+        }
+        grid
+    }
+
+    /// Calculate the centroid of the survey weighted by passage length, i.e. the mean of the
+    /// midpoints of all legs weighted by their length. Unlike
+    /// [`station_centroid`][`SurveyData::station_centroid`], this is not skewed towards areas
+    /// which were surveyed with many short legs, so is better suited to centring a map.
+    ///
+    /// If the survey has no legs with a non-zero length, such as data read from a .pos file, the
+    /// station centroid is returned instead. Returns [`None`] if there are no stations.
+    pub fn centroid(&self) -> Option<Point> {
+        let mut sum = Point::new(0.0, 0.0, 0.0);
+        let mut total = 0.0;
+        for edge in self.graph.edge_references() {
+            let length = edge.weight().length;
+            let (Some(from), Some(to)) = (
+                self.get_by_index(edge.source()),
+                self.get_by_index(edge.target()),
+            ) else {
+                continue;
+            };
+            let midpoint = from.borrow().coords.midpoint(&to.borrow().coords);
+            sum = sum + midpoint * length;
+            total += length;
+        }
+
+        if total > 0.0 {
+            Some(sum * (1.0 / total))
+        } else {
+            self.station_centroid()
+        }
+    }
+
+    /// Calculate the unweighted centroid of the survey, i.e. the mean position of all stations.
+    /// Returns [`None`] if there are no stations. See also [`centroid`][`SurveyData::centroid`].
+    pub fn station_centroid(&self) -> Option<Point> {
+        if self.stations.is_empty() {
+            return None;
+        }
+
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for station in &self.stations {
+            let coords = station.borrow().coords;
+            x += coords.x;
+            y += coords.y;
+            z += coords.z;
+        }
+        let n = self.stations.len() as f64;
+        Some(Point::new(x / n, y / n, z / n))
+    }
+
+    /// Retrieve the station nearest to the [centroid][`SurveyData::station_centroid`] of the
+    /// survey, such as for placing a single marker to represent a cave on a map. Returns [`None`]
+    /// if there are no stations.
+    pub fn central_station(&self) -> Option<RefStation> {
+        let centroid = self.station_centroid()?;
+        self.nearest_station(&centroid)
+    }
+
+    /// Retrieve all stations with an implausible [`LRUD`][`crate::station::LRUD`] measurement,
+    /// such as one recorded in the wrong units. A measurement is considered implausible if it is
+    /// greater than `max_reasonable` metres or is negative.
+    pub fn suspect_lrud(&self, max_reasonable: f64) -> Vec<RefStation> {
+        self.stations
+            .iter()
+            .filter(|station| {
+                let lrud = station.borrow().lrud.to_metres();
+                [lrud.left, lrud.right, lrud.up, lrud.down]
+                    .into_iter()
+                    .flatten()
+                    .any(|value| value > max_reasonable || value < 0.0)
+            })
+            .map(Rc::clone)
+            .collect()
+    }
+
+    /// Retrieve the number of legs connected to the station with the given label, or [`None`] if
+    /// no station has that label.
+    pub fn degree(&self, label: &str) -> Option<usize> {
+        let index = self.get_by_label(label)?.borrow().index;
+        Some(self.graph.neighbors(index).count())
+    }
+
+    /// Retrieve the length of the leg directly connecting the stations with the given labels, in
+    /// either direction. Returns [`None`] if either label does not match a station, if the
+    /// stations are not directly connected, or if they are connected by more than one leg.
+    pub fn leg_between(&self, a: &str, b: &str) -> Option<f64> {
+        let a = self.get_by_label(a)?.borrow().index;
+        let b = self.get_by_label(b)?.borrow().index;
+        let mut legs = self.graph.edges_connecting(a, b);
+        let leg = legs.next()?;
+        if legs.next().is_some() {
+            return None;
+        }
+        Some(leg.weight().length)
+    }
+
+    /// Retrieve all dead-end stations, which are stations with exactly one connected leg, in the
+    /// order they appear in the stations vector.
+    pub fn dead_ends(&self) -> Vec<RefStation> {
+        self.stations_by_degree(|degree| degree == 1)
+    }
+
+    /// Retrieve all junction stations, which are stations with three or more connected legs, in
+    /// the order they appear in the stations vector.
+    pub fn junctions(&self) -> Vec<RefStation> {
+        self.stations_by_degree(|degree| degree >= 3)
+    }
+
+    /// Calculate how many stations have each number of connected legs, such as for finding how
+    /// complex the junctions in a survey are. The result maps each number of legs to the number of
+    /// stations with that many legs, so the counts add up to the number of stations. Isolated
+    /// stations are counted with zero legs.
+    pub fn connectivity_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for index in self.graph.node_indices() {
+            *histogram
+                .entry(self.graph.neighbors(index).count())
+                .or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Retrieve the `n` stations with the most connected legs, in descending order of the number
+    /// of legs. Stations with the same number of legs are kept in the order they appear in the
+    /// stations vector.
+    pub fn most_connected(&self, n: usize) -> Vec<RefStation> {
+        let mut stations = self
+            .stations
+            .iter()
+            .map(|station| {
+                (
+                    self.graph.neighbors(station.borrow().index).count(),
+                    station,
+                )
+            })
+            .collect::<Vec<_>>();
+        stations.sort_by(|(a, _), (b, _)| b.cmp(a));
+        stations
+            .into_iter()
+            .take(n)
+            .map(|(_, station)| Rc::clone(station))
+            .collect()
+    }
+
+    /// Helper method to retrieve the stations whose number of connected legs matches the given
+    /// predicate.
+    fn stations_by_degree(&self, predicate: impl Fn(usize) -> bool) -> Vec<RefStation> {
+        self.stations
+            .iter()
+            .filter(|station| predicate(self.graph.neighbors(station.borrow().index).count()))
+            .map(Rc::clone)
+            .collect()
+    }
+
+    /// Replace the [length][`Leg::length`] of every leg with a value calculated from the
+    /// coordinates of its two stations, such as a cost which penalises climbing. The leg length is
+    /// the weight used by graph algorithms such as
+    /// [`shortest_path`][`SurveyData::shortest_path`], so this allows routes to be found using
+    /// other metrics than 3D distance:
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use survex_rs::read::load_from_path;
+    ///
+    /// let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+    /// // Prefer horizontal passage, treating each metre of height gained or lost as ten metres.
+    /// data.recompute_weights(|a, b| a.horizontal_distance(b) + 10.0 * a.vertical_distance(b));
+    /// ```
+    ///
+    /// The function is called with the coordinates of the two stations in the order they are
+    /// stored in the graph, which is not necessarily the direction in which the leg was surveyed.
+    /// Methods which report lengths, such as [`total_length`][`SurveyData::total_length`], will
+    /// use the new values, so call `recompute_weights(|a, b| a.distance(b))` to restore the
+    /// original lengths.
+    pub fn recompute_weights(&mut self, mut weight: impl FnMut(&Point, &Point) -> f64) {
+        for edge in self.graph.edge_indices() {
+            let Some((a, b)) = self.graph.edge_endpoints(edge) else {
+                continue;
+            };
+            let (Some(a), Some(b)) = (self.get_by_index(a), self.get_by_index(b)) else {
+                continue;
+            };
+            let length = weight(&a.borrow().coords, &b.borrow().coords);
+            self.graph[edge].length = length;
+        }
+        self.invalidate_caches();
+    }
+
+    /// Find the shortest route through the survey between the stations with the given labels,
+    /// such as for rescue planning. The route is returned as its total length along with the
+    /// ordered list of stations from `from` to `to`.
+    ///
+    /// Returns [`None`] if either label does not match a station, or if the stations are not
+    /// connected by any series of legs.
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<(f64, Vec<RefStation>)> {
+        self.warn_if_extended_elevation("shortest_path");
+        let start = self.get_by_label(from)?.borrow().index;
+        let goal = self.get_by_label(to)?.borrow().index;
+        // A* with a zero heuristic is Dijkstra's algorithm, but also returns the path taken.
+        let (length, path) = astar(
+            &self.graph,
+            start,
+            |index| index == goal,
+            |edge| edge.weight().length,
+            |_| 0.0,
+        )?;
+
+        let stations = path
+            .into_iter()
+            .map(|index| self.get_by_index(index))
+            .collect::<Option<Vec<_>>>()?;
+        Some((length, stations))
+    }
+
+    /// Calculate the distance along the passage from the station with the given label to every
+    /// other station, such as for labelling passages by their distance from an entrance.
+    ///
+    /// The distance to each station is the length of the [shortest route][`Self::shortest_path`]
+    /// to it from `entrance`, and is keyed by the index of the station in the graph. The given
+    /// station maps to zero. Stations which are not connected to it by any series of legs are not
+    /// included, and an empty map is returned if the label does not match a station.
+    pub fn chainage_from(&self, entrance: &str) -> HashMap<NodeIndex, f64> {
+        self.warn_if_extended_elevation("chainage_from");
+        let Some(start) = self.get_by_label(entrance) else {
+            return HashMap::new();
+        };
+        let start = start.borrow().index;
+        dijkstra(&self.graph, start, None, |edge| edge.weight().length)
+    }
+
+    /// Find the longest dead-end spur in the survey, such as for identifying the most promising
+    /// lead to push.
+    ///
+    /// A spur is a passage which runs from a junction (a station with three or more legs) to a
+    /// dead end (a station with exactly one leg), passing only through stations with exactly two
+    /// legs. The longest spur by total leg length is returned as the ordered list of stations from
+    /// the junction to the dead end, along with its length. Returns [`None`] if the survey has no
+    /// spurs.
+    pub fn longest_spur(&self) -> Option<(Vec<RefStation>, f64)> {
+        let degree = |index: NodeIndex| self.graph.edges(index).count();
+        let mut longest: Option<(Vec<NodeIndex>, f64)> = None;
+
+        for start in self
+            .graph
+            .node_indices()
+            .filter(|&index| degree(index) == 1)
+        {
+            let mut path = vec![start];
+            let mut length = 0.0;
+            let mut current = start;
+            let mut previous_edge = None;
+
+            let reached_junction = loop {
+                let Some(edge) = self
+                    .graph
+                    .edges(current)
+                    .find(|edge| Some(edge.id()) != previous_edge)
+                else {
+                    break false;
+                };
+                length += edge.weight().length;
+                current = if edge.source() == current {
+                    edge.target()
+                } else {
+                    edge.source()
+                };
+                previous_edge = Some(edge.id());
+                path.push(current);
+
+                match degree(current) {
+                    2 => continue,
+                    d => break d >= 3,
+                }
+            };
+
+            if reached_junction && longest.as_ref().is_none_or(|(_, l)| length > *l) {
+                path.reverse();
+                longest = Some((path, length));
+            }
+        }
+
+        let (path, length) = longest?;
+        let stations = self.station_map();
+        let path = path
+            .iter()
+            .filter_map(|index| stations.get(index).map(Rc::clone))
+            .collect();
+        Some((path, length))
+    }
+
+    /// Find the pitches (significant vertical drops) in the survey.
+    ///
+    /// A leg is considered part of a pitch if its inclination is at least
+    /// [`PITCH_MIN_INCLINATION`] degrees from horizontal and its vertical extent is at least
+    /// `min_drop` metres. Each pitch is returned as a tuple of the upper station, the lower station
+    /// and the vertical drop between them, sorted with the largest drop first.
+    pub fn pitches(&self, min_drop: f64) -> Vec<(RefStation, RefStation, f64)> {
+        let stations = self.station_map();
+        let mut pitches = Vec::new();
+
+        for edge in self.graph.edge_references() {
+            let (Some(a), Some(b)) = (stations.get(&edge.source()), stations.get(&edge.target()))
+            else {
+                continue;
+            };
+            let (p1, p2) = (a.borrow().coords, b.borrow().coords);
+
+            let drop = p1.vertical_distance(&p2);
+            let inclination = p1.gradient(&p2).abs();
+            if drop < min_drop || inclination < PITCH_MIN_INCLINATION {
+                continue;
+            }
+
+            let (upper, lower) = if p1.z >= p2.z { (a, b) } else { (b, a) };
+            pitches.push((Rc::clone(upper), Rc::clone(lower), drop));
+        }
+
+        pitches.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        pitches
+    }
+
+    /// Iterate over every leg in the survey. Each leg is returned as a tuple of the two stations
+    /// it connects and its length in metres.
+    pub fn iter_legs(&self) -> impl Iterator<Item = (RefStation, RefStation, f64)> + '_ {
+        self.graph.edge_references().filter_map(|edge| {
+            let from = self.get_by_index(edge.source())?;
+            let to = self.get_by_index(edge.target())?;
+            Some((from, to, edge.weight().length))
+        })
+    }
+
+    /// Retrieve the coordinates of every station as a contiguous array, such as for uploading
+    /// vertex positions to a GPU or passing them to another language.
+    ///
+    /// The coordinates are ordered by the [`NodeIndex`] of each station in the graph, so the
+    /// position of a station in the array is given by [`NodeIndex::index`] and the array can be
+    /// used with the indices from [`SurveyData::leg_index_array`]. Graph nodes which have no
+    /// station in the stations vector are given NaN coordinates to keep the array aligned.
+    pub fn coordinate_array(&self) -> Vec<[f64; 3]> {
+        self.graph
+            .node_indices()
+            .map(|index| {
+                self.get_by_index(index)
+                    .map_or([f64::NAN; 3], |station| station.borrow().coords.into())
+            })
+            .collect()
+    }
+
+    /// Retrieve every leg as a pair of indices into the array returned by
+    /// [`SurveyData::coordinate_array`], such as for drawing the survey as a set of lines. The
+    /// legs are in the order they are stored in the graph, and the indices are the
+    /// [`NodeIndex`] of each station.
+    pub fn leg_index_array(&self) -> Vec<[u32; 2]> {
+        self.graph
+            .edge_references()
+            .map(|edge| [edge.source().index() as u32, edge.target().index() as u32])
+            .collect()
+    }
+
+    /// Iterate over the legs of the given [`LegStyle`], such as the diving legs of a survey. Each
+    /// leg is returned as a tuple of the two stations it connects and a reference to the [`Leg`].
+    pub fn legs_by_style(
+        &self,
+        style: LegStyle,
+    ) -> impl Iterator<Item = (RefStation, RefStation, &Leg)> + '_ {
+        self.graph
+            .edge_references()
+            .filter(move |edge| edge.weight().style == style)
+            .filter_map(|edge| {
+                let from = self.get_by_index(edge.source())?;
+                let to = self.get_by_index(edge.target())?;
+                Some((from, to, edge.weight()))
+            })
+    }
+
+    /// Create a new [`SurveyData`] instance containing only the legs of the given [`LegStyle`],
+    /// along with the stations at either end of those legs. Stations are copied into the new
+    /// instance, so changes to them will not affect this instance.
+    pub fn filter_legs_by_style(&self, style: LegStyle) -> SurveyData {
+        let legs = self
+            .graph
+            .edge_references()
+            .filter(|edge| edge.weight().style == style)
+            .collect::<Vec<_>>();
+        let stations = legs
+            .iter()
+            .flat_map(|edge| [edge.source(), edge.target()])
+            .collect::<Vec<_>>();
+
+        self.extract(stations, legs.iter().map(|edge| edge.id()))
+    }
+
+    /// Create a new [`SurveyData`] instance containing only the stations whose labels start with
+    /// `prefix`, such as `nottsii.mainstreamway.` for one branch of a cave, along with the legs
+    /// between those stations. Stations are copied into the new instance and given new indices,
+    /// so changes to them will not affect this instance.
+    ///
+    /// The prefix is matched exactly, so `cave.inlet5` would also match `cave.inlet50.1`. Include
+    /// the trailing [separator][`SurveyData::separator`] to match a single survey.
+    pub fn subsurvey(&self, prefix: &str) -> SurveyData {
+        let stations = self
+            .stations
+            .iter()
+            .map(|station| station.borrow())
+            .filter(|station| station.label.starts_with(prefix))
+            .map(|station| station.index)
+            .collect::<Vec<_>>();
+        self.extract(stations, self.graph.edge_indices())
+    }
+
+    /// Helper method to create a new [`SurveyData`] instance from a subset of the stations and
+    /// legs in this instance. Stations are copied and given new indices in the new graph, in the
+    /// same relative order as in this graph. Legs are only included if both of their endpoints
+    /// are included in `stations`.
+    fn extract(
+        &self,
+        stations: impl IntoIterator<Item = NodeIndex>,
+        legs: impl IntoIterator<Item = EdgeIndex>,
+    ) -> SurveyData {
+        let mut indices = stations.into_iter().collect::<Vec<_>>();
+        indices.sort();
+        indices.dedup();
+
+        let station_map = self.station_map();
+        let mut data = SurveyData::new();
+        data.title = self.title.clone();
+        data.coordinate_system = self.coordinate_system.clone();
+        data.datestamp = self.datestamp.clone();
+        data.timestamp = self.timestamp;
+        data.format_version = self.format_version;
+        data.separator = self.separator;
+        data.extended_elevation = self.extended_elevation;
+        data.units = self.units;
+        let mut remap = HashMap::new();
+        for index in indices {
+            let Some(station) = station_map.get(&index) else {
+                continue;
+            };
+            let (_, new_index) = data.push_station(station.borrow().clone());
+            remap.insert(index, new_index);
+        }
+
+        for edge in legs {
+            let Some((a, b)) = self.graph.edge_endpoints(edge) else {
+                continue;
+            };
+            if let (Some(&a), Some(&b)) = (remap.get(&a), remap.get(&b)) {
+                data.graph.add_edge(a, b, self.graph[edge]);
+            }
+        }
+        data
+    }
+
+    /// Calculate summary statistics for each connected component of the survey graph, such as
+    /// each distinct cave system in a file. The components are sorted by total leg length, with
+    /// the longest first.
+    pub fn component_stats(&self) -> Vec<ComponentStats> {
+        let components = self.components();
+        let mut component_of = HashMap::new();
+        for (i, component) in components.iter().enumerate() {
+            for &index in component {
+                component_of.insert(index, i);
+            }
+        }
+
+        let mut legs = vec![(0, 0.0); components.len()];
+        for edge in self.graph.edge_references() {
+            if let Some(&i) = component_of.get(&edge.source()) {
+                legs[i].0 += 1;
+                legs[i].1 += edge.weight().length;
+            }
+        }
+
+        let stations = self.station_map();
+        let mut stats = components
+            .iter()
+            .zip(legs)
+            .filter_map(|(component, (legs, length))| {
+                let coords = component
+                    .iter()
+                    .filter_map(|index| stations.get(index).map(|s| s.borrow().coords));
+                Some(ComponentStats {
+                    stations: component.len(),
+                    legs,
+                    length,
+                    bounding_box: BoundingBox::from_points(coords)?,
+                })
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.length.total_cmp(&a.length));
+        stats
+    }
+
+    /// Group the stations of the survey into connected components, such as for detecting stray
+    /// stations which are not connected to the rest of the survey after a merge. Each component
+    /// contains the stations which can be reached from each other by following legs, sorted by
+    /// index. Components are ordered by the lowest station index they contain.
+    pub fn connected_components(&self) -> Vec<Vec<RefStation>> {
+        let stations = self.station_map();
+        self.components()
+            .into_iter()
+            .map(|component| {
+                component
+                    .iter()
+                    .filter_map(|index| stations.get(index).map(Rc::clone))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Find the loops in the survey, such as for calculating the length of each loop. Each loop is
+    /// returned as the ordered list of stations around it, without repeating the first station at
+    /// the end. A pair of stations joined by more than one leg forms a loop of two stations.
+    ///
+    /// Only a set of independent loops (the fundamental cycles of a spanning forest of the graph)
+    /// is returned, rather than every possible loop. Any other loop can be formed by combining
+    /// these, so a survey with `n` independent loops returns exactly `n` loops, but which loops
+    /// are returned depends on the order of the stations and legs. Legs which start and end at
+    /// the same station are ignored.
+    pub fn loops(&self) -> Vec<Vec<RefStation>> {
+        // Build a spanning forest using a breadth first search from each unvisited station,
+        // recording the parent and depth of each station along with the legs used.
+        let node_count = self.graph.node_count();
+        let mut parent = vec![None; node_count];
+        let mut depth = vec![0; node_count];
+        let mut visited = vec![false; node_count];
+        let mut tree_edges = HashSet::new();
+        for root in self.graph.node_indices() {
+            if visited[root.index()] {
+                continue;
+            }
+            visited[root.index()] = true;
+            let mut queue = VecDeque::from([root]);
+            while let Some(index) = queue.pop_front() {
+                for edge in self.graph.edges(index) {
+                    let next = edge.target();
+                    if !visited[next.index()] {
+                        visited[next.index()] = true;
+                        parent[next.index()] = Some(index);
+                        depth[next.index()] = depth[index.index()] + 1;
+                        tree_edges.insert(edge.id());
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        // Every leg which is not part of the forest closes a loop, made up of the leg and the
+        // paths through the forest from each of its stations to their lowest common ancestor.
+        let mut loops = Vec::new();
+        for edge in self.graph.edge_references() {
+            let (mut a, mut b) = (edge.source(), edge.target());
+            if a == b || tree_edges.contains(&edge.id()) {
+                continue;
+            }
+            let (mut up, mut down) = (vec![a], vec![b]);
+            while a != b {
+                if depth[a.index()] >= depth[b.index()] {
+                    a = parent[a.index()].expect("Station above the root of the forest");
+                    up.push(a);
+                } else {
+                    b = parent[b.index()].expect("Station above the root of the forest");
+                    down.push(b);
+                }
+            }
+            // Both paths end at the common ancestor, so only keep it once.
+            down.pop();
+            up.extend(down.into_iter().rev());
+            loops.push(
+                up.into_iter()
+                    .filter_map(|index| self.get_by_index(index))
+                    .collect(),
+            );
+        }
+        loops
+    }
+
+    /// Count the number of [connected components][`SurveyData::connected_components`] in the
+    /// survey.
+    pub fn component_count(&self) -> usize {
+        connected_components(&self.graph)
+    }
+
+    /// Helper method to group the indices of all stations in the graph into connected components.
+    /// Components are ordered by the lowest station index they contain, and the indices within
+    /// each component are sorted.
+    fn components(&self) -> Vec<Vec<NodeIndex>> {
+        let mut sets = UnionFind::new(self.graph.node_count());
+        for edge in self.graph.edge_references() {
+            sets.union(edge.source().index(), edge.target().index());
+        }
+
+        let mut components: Vec<Vec<NodeIndex>> = Vec::new();
+        let mut component_of_root = HashMap::new();
+        for index in self.graph.node_indices() {
+            let root = sets.find(index.index());
+            let i = *component_of_root.entry(root).or_insert_with(|| {
+                components.push(Vec::new());
+                components.len() - 1
+            });
+            components[i].push(index);
+        }
+        components
+    }
+
+    /// Calculate the position at the given distance, in metres, along a path of stations, such as
+    /// for animating movement along a route through the cave.
+    ///
+    /// The path is treated as a series of straight legs between consecutive stations. The leg
+    /// containing `distance` is found by accumulating leg lengths from the start of the path, and
+    /// the position is linearly interpolated along that leg. A distance of zero gives the first
+    /// station and a distance equal to the total length of the path gives the last station.
+    /// Returns [`None`] if the path is empty or `distance` is negative or beyond the end of the
+    /// path.
+    pub fn interpolate_along_path(&self, path: &[RefStation], distance: f64) -> Option<Point> {
+        let mut previous = path.first()?.borrow().coords;
+        if distance < 0.0 {
+            return None;
+        }
+
+        let mut remaining = distance;
+        for station in &path[1..] {
+            let next = station.borrow().coords;
+            let length = previous.distance(&next);
+            if remaining <= length {
+                let t = if length > 0.0 {
+                    remaining / length
+                } else {
+                    0.0
+                };
+                return Some(Point::new(
+                    previous.x + (next.x - previous.x) * t,
+                    previous.y + (next.y - previous.y) * t,
+                    previous.z + (next.z - previous.z) * t,
+                ));
+            }
+            remaining -= length;
+            previous = next;
+        }
+
+        // Allow for rounding errors when the distance is the total length of the path.
+        (remaining <= 1e-9).then_some(previous)
+    }
+
+    /// Determine the survey which a leg between the two given stations belongs to, such as for
+    /// grouping or styling legs by survey when exporting.
+    ///
+    /// This is the longest common survey prefix of the two station labels, ignoring the final
+    /// component of each label (the station name). Labels are split using the
+    /// [separator][`SurveyData::separator`] of the survey. For example, a leg between
+    /// `cave.upper.1` and `cave.upper.2` belongs to `cave.upper`, and a leg between
+    /// `cave.upper.5` and `cave.lower.1` belongs to `cave`.
+    ///
+    /// Returns [`None`] if the labels have no survey in common.
+    pub fn leg_survey(&self, from: &RefStation, to: &RefStation) -> Option<String> {
+        let (from, to) = (from.borrow(), to.borrow());
+        let separator = self.separator;
+        let (Some(from), Some(to)) = (from.parent_survey(separator), to.parent_survey(separator))
+        else {
+            return None;
+        };
+
+        let common = from
+            .split(separator)
+            .zip(to.split(separator))
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect::<Vec<_>>();
+
+        (!common.is_empty()).then(|| common.join(&separator.to_string()))
+    }
+
+    /// Helper method to build a map from the index of each station in the graph to the station.
+    fn station_map(&self) -> HashMap<NodeIndex, RefStation> {
+        self.stations
+            .iter()
+            .map(|station| (station.borrow().index, Rc::clone(station)))
+            .collect()
+    }
+
+    /// Helper method to retrieve the coordinates of both ends of every leg in the graph, along
+    /// with the length of the leg.
+    fn leg_coords(&self) -> Vec<(Point, Point, f64)> {
+        let coords = self
+            .stations
+            .iter()
+            .map(|station| {
+                let station = station.borrow();
+                (station.index, station.coords)
+            })
+            .collect::<HashMap<_, _>>();
+
+        self.graph
+            .edge_references()
+            .filter_map(|edge| {
+                let from = coords.get(&edge.source())?;
+                let to = coords.get(&edge.target())?;
+                Some((*from, *to, edge.weight().length))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::SurveyBuilder;
+    use crate::read::load_from_path;
+    use crate::station::{StationFlags, LRUD};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_worst_legs_by_error() {
+        let path = PathBuf::from("tests/data/0733.3d");
+        let data = load_from_path(path).unwrap();
+        let legs = data.worst_legs_by_error(10);
+        assert_eq!(legs.len(), 10);
+        for pair in legs.windows(2) {
+            assert!(pair[0].2 >= pair[1].2);
+        }
+
+        let legs_with_error = data
+            .graph
+            .edge_weights()
+            .filter(|leg| leg.error.is_some())
+            .count();
+        assert_eq!(data.worst_legs_by_error(usize::MAX).len(), legs_with_error);
+    }
+
+    #[test]
+    fn test_loop_errors() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert_eq!(data.loop_errors().len(), 19);
+
+        let legs_in_loops: usize = data
+            .loop_errors()
+            .iter()
+            .map(|loop_error| loop_error.n_legs)
+            .sum();
+        let legs_with_error = data
+            .graph
+            .edge_weights()
+            .filter(|leg| leg.error.is_some())
+            .count();
+        assert_eq!(legs_in_loops, legs_with_error);
+
+        let worst = data.worst_loop_by_horizontal_error().unwrap();
+        assert_eq!(worst.n_legs, 12);
+        assert_eq!(worst.length, 37.9);
+        assert_eq!(worst.error, 9.31);
+        assert_eq!(worst.horizontal_error, 11.04);
+        assert_eq!(worst.vertical_error, 3.42);
+
+        assert!(SurveyData::new().worst_loop_by_horizontal_error().is_none());
+    }
+
+    #[test]
+    fn test_new_length_vs() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let total: f64 = data.graph.edge_weights().map(|leg| leg.length).sum();
+
+        assert_eq!(data.new_length_vs(&data, 0.01), 0.0);
+        assert!((data.new_length_vs(&SurveyData::new(), 0.01) - total).abs() < 1e-6);
+
+        let other = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        assert!((data.new_length_vs(&other, 0.01) - total).abs() < 1e-6);
+
+        // A tolerance of zero or less matches identical coordinates only
+        assert_eq!(data.new_length_vs(&data, 0.0), 0.0);
+        assert_eq!(data.new_length_vs(&data, -1.0), 0.0);
+        assert!((data.new_length_vs(&other, 0.0) - total).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_length_histogram() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let histogram = data.length_histogram(2.5);
+        let total: usize = histogram.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, data.graph.edge_count());
+
+        for (i, (start, _)) in histogram.iter().enumerate() {
+            assert_eq!(*start, i as f64 * 2.5);
+        }
+
+        let longest = data
+            .graph
+            .edge_weights()
+            .map(|leg| leg.length)
+            .fold(0.0, f64::max);
+        assert_eq!(histogram.len(), (longest / 2.5).floor() as usize + 1);
+
+        assert!(data.length_histogram(0.0).is_empty());
+        assert!(data.length_histogram(f64::NAN).is_empty());
+        assert!(data.length_histogram(f64::INFINITY).is_empty());
+        assert!(data.length_histogram(1e-12).is_empty());
+        assert!(SurveyData::new().length_histogram(1.0).is_empty());
+    }
+
+    #[test]
+    fn test_add_or_record_conflict() {
+        let mut data = SurveyData::new();
+        let (_, first) = data.add_or_record_conflict(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, same) = data.add_or_record_conflict(Point::new(0.0, 0.0, 0.0), "a.1");
+        assert_eq!(first, same);
+        assert!(data.label_conflicts.is_empty());
+
+        let (_, second) = data.add_or_record_conflict(Point::new(1.0, 0.0, 0.0), "a.1");
+        assert_ne!(first, second);
+        assert_eq!(data.stations.len(), 2);
+        assert_eq!(
+            data.label_conflicts,
+            vec![(
+                String::from("a.1"),
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0)
+            )]
+        );
+
+        // A third listing matching the second conflicting station is not another conflict
+        let (_, third) = data.add_or_record_conflict(Point::new(1.0, 0.0, 0.0), "a.1");
+        assert_eq!(second, third);
+        assert_eq!(data.stations.len(), 2);
+        assert_eq!(data.label_conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_depth_below_surface() {
+        let mut data = SurveyData::new();
+        for (label, coords) in [
+            ("top.1", Point::new(0.0, 0.0, 100.0)),
+            ("top.2", Point::new(50.0, 0.0, 120.0)),
+        ] {
+            let (station, _) = data.add_or_update(coords, label);
+            station.borrow_mut().flags.insert(StationFlags::SURFACE);
+        }
+        let (_, a) = data.add_or_update(Point::new(1.0, 0.0, 40.0), "cave.1");
+        let (_, b) = data.add_or_update(Point::new(49.0, 0.0, 50.0), "cave.2");
+        for station in &data.stations[2..] {
+            station.borrow_mut().flags.insert(StationFlags::UNDERGROUND);
+        }
+
+        let depths = data.depth_below_surface();
+        assert_eq!(depths.len(), 2);
+        assert_eq!(depths[&a], 60.0);
+        assert_eq!(depths[&b], 70.0);
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let depths = data.depth_below_surface();
+        let count = data
+            .stations
+            .iter()
+            .filter(|station| station.borrow().underground())
+            .count();
+        assert_eq!(depths.len(), count);
+    }
+
+    #[test]
+    fn test_pitches() {
+        let mut data = SurveyData::new();
+        let (_, top) = data.add_or_update(Point::new(0.0, 0.0, 100.0), "a.1");
+        let (_, bottom) = data.add_or_update(Point::new(1.0, 0.0, 80.0), "a.2");
+        let (_, slope) = data.add_or_update(Point::new(21.0, 0.0, 60.0), "a.3");
+        let (_, step) = data.add_or_update(Point::new(21.0, 0.0, 58.0), "a.4");
+        for (a, b) in [(top, bottom), (bottom, slope), (slope, step)] {
+            let length = data.stations[a.index()]
+                .borrow()
+                .coords
+                .distance(&data.stations[b.index()].borrow().coords);
+            data.graph.add_edge(a, b, Leg::new(length));
+        }
+
+        let pitches = data.pitches(5.0);
+        assert_eq!(pitches.len(), 1);
+        assert_eq!(pitches[0].0.borrow().label, "a.1");
+        assert_eq!(pitches[0].1.borrow().label, "a.2");
+        assert_eq!(pitches[0].2, 20.0);
+
+        assert_eq!(data.pitches(1.0).len(), 2);
+
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let pitches = data.pitches(10.0);
+        for pair in pitches.windows(2) {
+            assert!(pair[0].2 >= pair[1].2);
+        }
+        for (upper, lower, drop) in pitches {
+            assert!(drop >= 10.0);
+            assert!(upper.borrow().coords.z > lower.borrow().coords.z);
+        }
+    }
+
+    #[test]
+    fn test_iter_legs() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        assert_eq!(data.iter_legs().count(), data.graph.edge_count());
+        let total = data.iter_legs().map(|(_, _, length)| length).sum::<f64>();
+        assert!((total - data.total_length(true, true)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_coordinate_and_leg_index_arrays() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let coordinates = data.coordinate_array();
+        let legs = data.leg_index_array();
+        assert_eq!(coordinates.len(), data.stations.len());
+        assert_eq!(legs.len(), data.graph.edge_count());
+
+        for station in data.iter_stations() {
+            let coords: [f64; 3] = station.coords.into();
+            assert_eq!(coordinates[station.index.index()], coords);
+        }
+        for ([from, to], (a, b, _)) in legs.iter().zip(data.iter_legs()) {
+            assert_eq!(
+                coordinates[*from as usize],
+                <[f64; 3]>::from(a.borrow().coords)
+            );
+            assert_eq!(
+                coordinates[*to as usize],
+                <[f64; 3]>::from(b.borrow().coords)
+            );
+        }
+    }
+
+    #[test]
+    fn test_legs_by_style() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let diving = data.legs_by_style(LegStyle::Diving).collect::<Vec<_>>();
+        assert_eq!(diving.len(), 23);
+        for (from, to, leg) in diving {
+            assert_eq!(leg.style, LegStyle::Diving);
+            let distance = from.borrow().coords.distance(&to.borrow().coords);
+            assert!((distance - leg.length).abs() < 1e-6);
+        }
+        assert_eq!(data.legs_by_style(LegStyle::Cartesian).count(), 0);
+    }
+
+    #[test]
+    fn test_filter_legs_by_style() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let diving = data.filter_legs_by_style(LegStyle::Diving);
+        assert_eq!(diving.graph.edge_count(), 23);
+        assert!(diving
+            .graph
+            .edge_weights()
+            .all(|leg| leg.style == LegStyle::Diving));
+
+        for (i, station) in diving.stations.iter().enumerate() {
+            let station = station.borrow();
+            assert_eq!(station.index.index(), i);
+            assert_eq!(diving.graph[station.index], station.label);
+            let original = data.get_by_label(&station.label).unwrap();
+            assert_eq!(original.borrow().coords, station.coords);
+        }
+
+        let none = data.filter_legs_by_style(LegStyle::CylPolar);
+        assert_eq!(none.stations.len(), 0);
+        assert_eq!(none.graph.edge_count(), 0);
+    }
+
+    #[test]
+    fn test_stations_by_tile() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let tiles = data.stations_by_tile(100.0);
+        let total: usize = tiles.values().map(|stations| stations.len()).sum();
+        assert_eq!(total, data.stations.len());
+
+        let entrance = &tiles[&(666, 783)];
+        assert!(entrance
+            .iter()
+            .any(|station| station.borrow().label == "nottsii.entrance"));
+        for (tile, stations) in tiles {
+            for station in stations {
+                assert_eq!(station.borrow().coords.tile(100.0), tile);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let nottsii = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let other = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let bbox = nottsii.bounding_box().unwrap();
+
+        assert!(nottsii.overlaps(&nottsii));
+        assert_eq!(nottsii.spatial_overlap_volume(&nottsii), bbox.volume());
+        assert!(!nottsii.overlaps(&other));
+        assert_eq!(nottsii.spatial_overlap_volume(&other), 0.0);
+        assert!(!nottsii.overlaps(&SurveyData::new()));
+    }
+
+    #[test]
+    fn test_length_depth_profile() {
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(4.0, 0.0, 0.0), "a.2");
+        let (_, c) = data.add_or_update(Point::new(4.0, 0.0, -10.0), "a.3");
+        let (_, d) = data.add_or_update(Point::new(4.0, 0.0, -30.0), "a.4");
+        data.graph.add_edge(a, b, Leg::new(4.0));
+        data.graph.add_edge(b, c, Leg::new(10.0));
+        data.graph.add_edge(c, d, Leg::new(20.0));
+
+        assert_eq!(
+            data.length_depth_profile(10.0),
+            vec![(10.0, 14.0), (20.0, 14.0), (30.0, 34.0)]
+        );
+        assert!(data.length_depth_profile(0.0).is_empty());
+        assert!(data.length_depth_profile(f64::NAN).is_empty());
+        assert!(data.length_depth_profile(f64::INFINITY).is_empty());
+        assert!(data.length_depth_profile(1e-12).is_empty());
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let total: f64 = data.graph.edge_weights().map(|leg| leg.length).sum();
+        let profile = data.length_depth_profile(5.0);
+        assert!((profile.last().unwrap().1 - total).abs() < 1e-6);
+        for pair in profile.windows(2) {
+            assert!(pair[0].1 <= pair[1].1);
+        }
+    }
+
+    #[test]
+    fn test_isolated_stations() {
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        data.add_or_update(Point::new(5.0, 5.0, 5.0), "a.3");
+        data.graph.add_edge(a, b, Leg::new(1.0));
+
+        let isolated = data.isolated_stations();
+        assert_eq!(isolated.len(), 1);
+        assert_eq!(isolated[0].borrow().label, "a.3");
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        for station in data.isolated_stations() {
+            let index = station.borrow().index;
+            assert_eq!(data.graph.neighbors(index).count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_passage_walls() {
+        // A straight passage heading north, so the right wall is to the east.
+        let mut data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.2", Point::new(0.0, 10.0, 0.0), StationFlags::empty())
+            .add_station("a.3", Point::new(0.0, 20.0, 0.0), StationFlags::empty())
+            .add_station("a.4", Point::new(0.0, 30.0, 0.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .add_leg("a.2", "a.3")
+            .add_leg("a.3", "a.4")
+            .build()
+            .unwrap();
+        for label in ["a.1", "a.2", "a.3"] {
+            data.get_by_label(label).unwrap().borrow_mut().lrud = LRUD::new(1.0, 2.0, 3.0, 0.5);
+        }
+        data.get_by_label("a.4").unwrap().borrow_mut().lrud = LRUD::new(1.0, 2.0, 3.0, -1.0);
+        let a = data.get_by_label("a.1").unwrap().borrow().index;
+        let b = data.get_by_label("a.3").unwrap().borrow().index;
+        let mut splay = Leg::new(20.0);
+        splay.flags.splay = true;
+        data.graph.add_edge(a, b, splay);
+
+        let walls = data.passage_walls();
+        assert_eq!(walls.len(), 2);
+        let wall = walls[0];
+        assert_eq!((wall.from, wall.to), (a, data.stations[1].borrow().index));
+        assert_eq!(
+            wall.start,
+            [
+                Point::new(-1.0, 0.0, 3.0),
+                Point::new(2.0, 0.0, 3.0),
+                Point::new(2.0, 0.0, -0.5),
+                Point::new(-1.0, 0.0, -0.5),
+            ]
+        );
+        assert_eq!(wall.end[2], Point::new(2.0, 10.0, -0.5));
+        assert_eq!(
+            wall.plan(),
+            [
+                Point::new(-1.0, 0.0, 3.0),
+                Point::new(-1.0, 10.0, 3.0),
+                Point::new(2.0, 10.0, 3.0),
+                Point::new(2.0, 0.0, 3.0),
+            ]
+        );
+        assert_eq!(
+            wall.section(),
+            [
+                Point::new(-1.0, 0.0, 3.0),
+                Point::new(-1.0, 10.0, 3.0),
+                Point::new(-1.0, 10.0, -0.5),
+                Point::new(-1.0, 0.0, -0.5),
+            ]
+        );
+        assert_eq!(walls[1].start[0], Point::new(-1.0, 10.0, 3.0));
+    }
+
+    #[test]
+    fn test_leg_between() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let (a, b, c) = (
+            "nottsii.inlet7.inlet7-4.001",
+            "nottsii.inlet7.inlet7-4.002",
+            "nottsii.inlet7.inlet7-4.003",
+        );
+        let length = data.leg_between(a, b).unwrap();
+        assert!((length - 5.7926).abs() < 1e-4, "{}", length);
+        assert_eq!(data.leg_between(b, a), Some(length));
+        assert_eq!(data.leg_between(a, c), None);
+        assert_eq!(data.leg_between(a, "nottsii.missing"), None);
+
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        data.graph.add_edge(a, b, Leg::new(1.0));
+        assert_eq!(data.leg_between("a.1", "a.2"), Some(1.0));
+        data.graph.add_edge(b, a, Leg::new(1.0));
+        assert_eq!(data.leg_between("a.1", "a.2"), None);
+    }
+
+    #[test]
+    fn test_chainage_from() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let entrance = data
+            .get_by_label("nottsii.entrance")
+            .unwrap()
+            .borrow()
+            .index;
+        let chainage = data.chainage_from("nottsii.entrance");
+        assert_eq!(chainage[&entrance], 0.0);
+
+        let edge = data.graph.edges(entrance).next().unwrap();
+        let neighbour = if edge.source() == entrance {
+            edge.target()
+        } else {
+            edge.source()
+        };
+        assert_eq!(chainage[&neighbour], edge.weight().length);
+
+        let station = data.get_by_label("nottsii.inlet7.inlet7-4.002").unwrap();
+        let (length, _) = data
+            .shortest_path("nottsii.entrance", &station.borrow().label)
+            .unwrap();
+        assert!((chainage[&station.borrow().index] - length).abs() < 1e-9);
+        assert!(data.chainage_from("nottsii.missing").is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.2", Point::new(1.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.3", Point::new(2.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("b.1", Point::new(1.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("b.2", Point::new(f64::NAN, 0.0, 0.0), StationFlags::empty())
+            .add_station("orphan", Point::new(5.0, 5.0, 5.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .add_leg("a.2", "a.3")
+            .add_leg("a.3", "b.1")
+            .add_leg("b.1", "b.2")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            data.validate(),
+            vec![
+                Warning::IsolatedStation(String::from("orphan")),
+                Warning::NanCoordinates(String::from("b.2")),
+                Warning::DuplicateCoordinates(vec![String::from("a.2"), String::from("b.1")]),
+            ]
+        );
+        let orphans = data.orphan_stations();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].borrow().label, "orphan");
+
+        let data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.2", Point::new(1.0, 0.0, 0.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .build()
+            .unwrap();
+        assert!(data.validate().is_empty());
+    }
+
+    #[test]
+    fn test_principal_axis() {
+        let line = |points: &[(f64, f64)]| {
+            let mut data = SurveyData::new();
+            for (i, (x, y)) in points.iter().enumerate() {
+                data.add_or_update(Point::new(*x, *y, 0.0), &format!("a.{}", i));
+            }
+            data
+        };
+
+        let bearing = line(&[(0.0, 0.0), (0.0, 10.0), (1.0, 20.0), (0.0, 30.0)])
+            .principal_axis()
+            .unwrap();
+        assert!(!(2.0..=178.0).contains(&bearing));
+
+        let bearing = line(&[(0.0, 0.0), (10.0, 0.0), (20.0, 0.0)])
+            .principal_axis()
+            .unwrap();
+        assert!((bearing - 90.0).abs() < 1e-9);
+
+        let bearing = line(&[(0.0, 0.0), (10.0, 10.0), (20.0, 20.0)])
+            .principal_axis()
+            .unwrap();
+        assert!((bearing - 45.0).abs() < 1e-9);
+
+        let bearing = line(&[(0.0, 0.0), (-10.0, 10.0), (-20.0, 20.0)])
+            .principal_axis()
+            .unwrap();
+        assert!((bearing - 135.0).abs() < 1e-9);
+
+        assert_eq!(line(&[(0.0, 0.0)]).principal_axis(), None);
+        assert_eq!(line(&[(1.0, 1.0), (1.0, 1.0)]).principal_axis(), None);
+    }
+
+    #[test]
+    fn test_density_grid() {
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(4.0, 0.0, 0.0), "a.2");
+        let (_, c) = data.add_or_update(Point::new(4.0, 25.0, -5.0), "a.3");
+        data.graph.add_edge(a, b, Leg::new(4.0));
+        data.graph.add_edge(b, c, Leg::new(25.5));
+
+        let grid = data.density_grid(10.0, Projection::Plan);
+        assert_eq!(grid, vec![vec![4.0], vec![25.5], vec![0.0]]);
+
+        let grid = data.density_grid(2.0, Projection::Elevation(0.0));
+        assert_eq!(grid.len(), 3);
+        assert_eq!(grid[0].len(), 3);
+        assert_eq!(grid[1][2], 25.5);
+        assert_eq!(grid[2][1], 4.0);
+
+        assert!(data.density_grid(0.0, Projection::Plan).is_empty());
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let total: f64 = data.graph.edge_weights().map(|leg| leg.length).sum();
+        let grid = data.density_grid(25.0, Projection::Plan);
+        let sum: f64 = grid.iter().flatten().sum();
+        assert!((sum - total).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_central_station() {
+        let mut data = SurveyData::new();
+        assert!(data.central_station().is_none());
+
+        data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        data.add_or_update(Point::new(9.0, 1.0, 0.0), "a.2");
+        data.add_or_update(Point::new(12.0, 0.0, 0.0), "a.3");
+        data.add_or_update(Point::new(20.0, 2.0, 0.0), "a.4");
+
+        assert_eq!(data.station_centroid(), Some(Point::new(10.25, 0.75, 0.0)));
+        assert_eq!(data.central_station().unwrap().borrow().label, "a.2");
+    }
+
+    #[test]
+    fn test_centroid() {
+        let mut data = SurveyData::new();
+        assert!(data.centroid().is_none());
+
+        // A 10m leg along the x axis and a densely surveyed 2m passage along the y axis. The
+        // station centroid is pulled towards the dense passage, but the length weighted centroid
+        // is not.
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        assert_eq!(data.centroid(), Some(Point::new(0.0, 0.0, 0.0)));
+        let (_, b) = data.add_or_update(Point::new(10.0, 0.0, 0.0), "a.2");
+        data.graph.add_edge(a, b, Leg::new(10.0));
+        let mut previous = a;
+        for i in 1..=4 {
+            let label = format!("b.{}", i);
+            let (_, index) = data.add_or_update(Point::new(0.0, 0.5 * i as f64, 0.0), &label);
+            data.graph.add_edge(previous, index, Leg::new(0.5));
+            previous = index;
+        }
+
+        let centroid = data.centroid().unwrap();
+        assert!(centroid.distance(&Point::new(25.0 / 6.0, 1.0 / 6.0, 0.0)) < 1e-9);
+        assert_eq!(
+            data.station_centroid(),
+            Some(Point::new(10.0 / 6.0, 5.0 / 6.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn test_suspect_lrud() {
+        let mut data = SurveyData::new();
+        let (a, _) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (b, _) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        let (c, _) = data.add_or_update(Point::new(2.0, 0.0, 0.0), "a.3");
+        a.borrow_mut().lrud.update(1.0, 2.0, 0.5, -1.0);
+        b.borrow_mut().lrud.update(1.0, 500.0, 0.5, 0.5);
+        c.borrow_mut().lrud.left = Some(-2.0);
+
+        let suspect = data.suspect_lrud(50.0);
+        let labels = suspect
+            .iter()
+            .map(|station| station.borrow().label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["a.2", "a.3"]);
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert!(data.suspect_lrud(f64::INFINITY).is_empty());
+        assert!(!data.suspect_lrud(0.0).is_empty());
+    }
+
+    #[test]
+    fn test_longest_spur() {
+        let mut data = SurveyData::new();
+        let mut add =
+            |label: &str, x: f64, y: f64| data.add_or_update(Point::new(x, y, 0.0), label).1;
+        let junction = add("a.j", 0.0, 0.0);
+        let short = add("a.1", 5.0, 0.0);
+        let middle = add("a.2", 0.0, 10.0);
+        let end = add("a.3", 0.0, 13.0);
+        let tiny = add("a.4", -2.0, 0.0);
+        let (far1, far2) = (add("b.1", 100.0, 0.0), add("b.2", 200.0, 0.0));
+        for (a, b, length) in [
+            (junction, short, 5.0),
+            (junction, middle, 10.0),
+            (middle, end, 3.0),
+            (junction, tiny, 2.0),
+            (far1, far2, 100.0),
+        ] {
+            data.graph.add_edge(a, b, Leg::new(length));
+        }
+
+        let (path, length) = data.longest_spur().unwrap();
+        let labels = path
+            .iter()
+            .map(|station| station.borrow().label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["a.j", "a.2", "a.3"]);
+        assert_eq!(length, 13.0);
+
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        data.graph.add_edge(a, b, Leg::new(1.0));
+        assert!(data.longest_spur().is_none());
+    }
+
+    #[test]
+    fn test_merge_welds_shared_labels() {
+        let mut a = SurveyData::new();
+        let (_, a1) = a.add_or_update(Point::new(0.0, 0.0, 0.0), "cave.1");
+        let (_, a2) = a.add_or_update(Point::new(1.0, 0.0, 0.0), "cave.2");
+        a.graph.add_edge(a1, a2, Leg::new(1.0));
+
+        let mut b = SurveyData::new();
+        let (_, b2) = b.add_or_update(Point::new(1.0, 0.0, 0.0), "cave.2");
+        let (_, b3) = b.add_or_update(Point::new(1.0, 2.0, 0.0), "cave.3");
+        b.graph.add_edge(b2, b3, Leg::new(2.0));
+
+        a.merge(b, LabelCollision::Weld);
+        assert_eq!(a.stations.len(), 3);
+        assert_eq!(a.graph.edge_count(), 2);
+
+        let joined = a.get_by_label("cave.2").unwrap().borrow().index;
+        assert_eq!(a.graph.neighbors(joined).count(), 2);
+        let added = a.get_by_label("cave.3").unwrap();
+        assert_eq!(added.borrow().index.index(), 2);
+        assert_eq!(a.graph[added.borrow().index], "cave.3");
+    }
+
+    #[test]
+    fn test_merge_bundled_files() {
+        let nottsii = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let (stations, legs) = (nottsii.stations.len(), nottsii.graph.edge_count());
+        let other = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let (other_stations, other_legs) = (other.stations.len(), other.graph.edge_count());
+        let components = nottsii.component_count() + other.component_count();
+
+        let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        data.merge(other, LabelCollision::Weld);
+        assert_eq!(data.stations.len(), stations + other_stations);
+        assert_eq!(data.graph.edge_count(), legs + other_legs);
+        assert_eq!(data.component_count(), components);
+
+        // Merging a file with itself welds every station, but prefixing keeps them apart.
+        let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        data.merge(
+            load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap(),
+            LabelCollision::Weld,
+        );
+        assert_eq!(data.stations.len(), stations);
+        assert_eq!(data.graph.edge_count(), legs * 2);
+
+        let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        data.merge(
+            load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap(),
+            LabelCollision::Prefix(String::from("copy")),
+        );
+        assert_eq!(data.stations.len(), stations * 2);
+        assert_eq!(data.graph.edge_count(), legs * 2);
+        let copy = data.get_by_label("copy.nottsii.entrance").unwrap();
+        assert_eq!(copy.borrow().index.index(), stations + 1901);
+        assert_eq!(data.graph[copy.borrow().index], "copy.nottsii.entrance");
+    }
+
+    #[test]
+    fn test_retain_stations() {
+        let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let underground = data
+            .iter_stations()
+            .filter(|station| station.underground())
+            .count();
+        let legs = data
+            .graph
+            .edge_references()
+            .filter(|edge| {
+                let underground = |index| data.get_by_index(index).unwrap().borrow().underground();
+                underground(edge.source()) && underground(edge.target())
+            })
+            .count();
+        let kept = data
+            .stations
+            .iter()
+            .rev()
+            .find(|station| station.borrow().underground())
+            .map(Rc::clone)
+            .unwrap();
+        let label = kept.borrow().label.clone();
+        assert!(underground < data.stations.len());
+
+        data.retain_stations(|station| station.underground());
+        assert_eq!(data.stations.len(), underground);
+        assert_eq!(data.graph.node_count(), underground);
+        assert_eq!(data.graph.edge_count(), legs);
+        for (i, station) in data.iter_stations().enumerate() {
+            assert!(station.underground());
+            assert_eq!(station.index.index(), i);
+            assert_eq!(data.graph[station.index], station.label);
+            let found = data.get_by_label(&station.label).unwrap();
+            assert_eq!(found.borrow().index, station.index);
+        }
+
+        assert!(data.get_by_label("nottsii.entrance").is_none());
+
+        // References to retained stations are updated with their new index.
+        let retained = data.get_by_index(kept.borrow().index).unwrap();
+        assert!(Rc::ptr_eq(&retained, &kept));
+        assert_eq!(retained.borrow().label, label);
+
+        data.retain_stations(|_| false);
+        assert!(data.stations.is_empty());
+        assert_eq!(data.graph.edge_count(), 0);
+        assert!(data.get_by_label(&label).is_none());
+    }
+
+    #[test]
+    fn test_search_labels() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let matches = data.search_labels("voldemort_entrence", 3);
+        assert_eq!(matches.len(), 3);
+        assert!(matches[0].0.borrow().label.ends_with(".voldemort_entrance"));
+        assert!(matches.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        let matches = data.search_labels("NOTTSII.ENTRANCE", 1);
+        assert_eq!(matches[0].0.borrow().label, "nottsii.entrance");
+        assert_eq!(matches[0].1, 1.0);
+
+        assert!(data.search_labels("entrance", 0).is_empty());
+        assert!(SurveyData::new().search_labels("entrance", 5).is_empty());
+    }
+
+    #[test]
+    fn test_extent() {
+        let data = load_from_path(PathBuf::from("tests/data/anonymous.pos")).unwrap();
+        let (a, b, distance) = data.extent().unwrap();
+        assert_eq!(a.borrow().label, "cave.1");
+        assert_eq!(b.borrow().coords, Point::new(107.0, 206.25, 46.5));
+        assert!((distance - 100.3125_f64.sqrt()).abs() < 1e-9);
+
+        let mut data = SurveyData::new();
+        assert!(data.extent().is_none());
+        data.add_or_update(Point::new(1.0, 2.0, 3.0), "a.1");
+        assert!(data.extent().is_none());
+    }
+
+    #[test]
+    fn test_station_sets() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let underground = data.underground_stations();
+        let surface = data.surface_stations();
+        let entrances = data.entrances();
+        assert_eq!(underground.len(), 1887);
+        assert_eq!(surface.len(), 2);
+        assert!(underground
+            .iter()
+            .all(|station| station.borrow().underground()));
+        assert!(surface.iter().all(|station| station.borrow().surface()));
+
+        let labels = entrances
+            .iter()
+            .map(|station| station.borrow().label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                "nottsii.thecupcake_entrance",
+                "nottsii.voldemort_entrance",
+                "nottsii.entrance"
+            ]
+        );
+        assert!(SurveyData::new().entrances().is_empty());
+    }
+
+    #[test]
+    fn test_bearing_histogram() {
+        let data = SurveyBuilder::new()
+            .add_station("a.0", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.n", Point::new(0.0, 10.0, 0.0), StationFlags::empty())
+            .add_station("a.e", Point::new(5.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.s", Point::new(0.0, -3.0, -4.0), StationFlags::empty())
+            .add_station("a.nw", Point::new(-1.0, 1.0, 0.0), StationFlags::empty())
+            .add_station("a.up", Point::new(0.0, 0.0, 20.0), StationFlags::empty())
+            .add_leg("a.0", "a.n")
+            .add_leg("a.0", "a.e")
+            .add_leg("a.0", "a.s")
+            .add_leg("a.0", "a.nw")
+            .add_leg("a.0", "a.up")
+            .build()
+            .unwrap();
+
+        let histogram = data.bearing_histogram(4);
+        let expected = [10.0, 5.0, 5.0, 2.0_f64.sqrt()];
+        assert_eq!(histogram.len(), 4);
+        for (total, expected) in histogram.iter().zip(expected) {
+            assert!((total - expected).abs() < 1e-9, "{} != {}", total, expected);
+        }
+        assert_eq!(data.bearing_histogram(8)[7], 2.0_f64.sqrt());
+        assert!(data.bearing_histogram(0).is_empty());
+    }
+
+    #[test]
+    fn test_station_trend_and_gradient() {
+        // A straight passage heading 60 degrees from north and rising at 10 degrees, with a splay
+        // from the middle station which should be ignored.
+        let (east, north) = 60.0_f64.to_radians().sin_cos();
+        let rise = 10.0 * 10.0_f64.to_radians().tan();
+        let mut builder = SurveyBuilder::new();
+        for i in 0..4 {
+            let i = f64::from(i);
+            let coords = Point::new(10.0 * east * i, 10.0 * north * i, rise * i);
+            builder = builder.add_station(&format!("a.{}", i), coords, StationFlags::empty());
+        }
+        let mut data = builder
+            .add_station("a.splay", Point::new(0.0, 20.0, 0.0), StationFlags::empty())
+            .add_station("a.lone", Point::new(50.0, 50.0, 0.0), StationFlags::empty())
+            .add_station("b.0", Point::new(0.0, 0.0, 100.0), StationFlags::empty())
+            .add_station("b.1", Point::new(0.0, 0.0, 80.0), StationFlags::empty())
+            .add_leg("a.0", "a.1")
+            .add_leg("a.2", "a.1")
+            .add_leg("a.2", "a.3")
+            .add_leg("b.0", "b.1")
+            .build()
+            .unwrap();
+        let a = data.get_by_label("a.1").unwrap().borrow().index;
+        let b = data.get_by_label("a.splay").unwrap().borrow().index;
+        let mut splay = Leg::new(20.0);
+        splay.flags.splay = true;
+        data.graph.add_edge(a, b, splay);
+
+        for label in ["a.0", "a.1", "a.2", "a.3"] {
+            let trend = data.station_trend(label).unwrap();
+            assert!((trend - 60.0).abs() < 1e-9, "{}: {}", label, trend);
+            let gradient = data.station_gradient(label).unwrap();
+            assert!((gradient - 10.0).abs() < 1e-9, "{}: {}", label, gradient);
+        }
+
+        assert_eq!(data.station_trend("b.0"), None);
+        assert_eq!(data.station_gradient("b.0"), Some(90.0));
+        assert_eq!(data.station_trend("a.lone"), None);
+        assert_eq!(data.station_gradient("a.lone"), None);
+        assert_eq!(data.station_trend("a.missing"), None);
+        assert_eq!(data.station_gradient("a.missing"), None);
+    }
+
+    #[test]
+    fn test_component_stats() {
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(3.0, 4.0, 0.0), "a.2");
+        let (_, c) = data.add_or_update(Point::new(100.0, 0.0, 0.0), "b.1");
+        let (_, d) = data.add_or_update(Point::new(100.0, 0.0, -20.0), "b.2");
+        let (_, e) = data.add_or_update(Point::new(110.0, 0.0, -20.0), "b.3");
+        data.add_or_update(Point::new(500.0, 0.0, 0.0), "c.1");
+        data.graph.add_edge(a, b, Leg::new(5.0));
+        data.graph.add_edge(c, d, Leg::new(20.0));
+        data.graph.add_edge(d, e, Leg::new(10.0));
+
+        let stats = data.component_stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(
+            (stats[0].stations, stats[0].legs, stats[0].length),
+            (3, 2, 30.0)
+        );
+        assert_eq!(stats[0].bounding_box.min, Point::new(100.0, 0.0, -20.0));
+        assert_eq!(stats[0].bounding_box.max, Point::new(110.0, 0.0, 0.0));
+        assert_eq!(
+            (stats[1].stations, stats[1].legs, stats[1].length),
+            (2, 1, 5.0)
+        );
+        assert_eq!(
+            (stats[2].stations, stats[2].legs, stats[2].length),
+            (1, 0, 0.0)
+        );
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let stats = data.component_stats();
+        assert_eq!(stats.iter().map(|c| c.stations).sum::<usize>(), 1904);
+        assert_eq!(stats.iter().map(|c| c.legs).sum::<usize>(), 1782);
+        for pair in stats.windows(2) {
+            assert!(pair[0].length >= pair[1].length);
+        }
+    }
+
+    #[test]
+    fn test_interpolate_along_path() {
+        let mut data = SurveyData::new();
+        let (a, _) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (b, _) = data.add_or_update(Point::new(10.0, 0.0, 0.0), "a.2");
+        let (c, _) = data.add_or_update(Point::new(10.0, 0.0, -5.0), "a.3");
+        let path = vec![a, b, c];
+
+        let at = |distance| data.interpolate_along_path(&path, distance);
+        assert_eq!(at(0.0), Some(Point::new(0.0, 0.0, 0.0)));
+        assert_eq!(at(2.5), Some(Point::new(2.5, 0.0, 0.0)));
+        assert_eq!(at(10.0), Some(Point::new(10.0, 0.0, 0.0)));
+        assert_eq!(at(12.0), Some(Point::new(10.0, 0.0, -2.0)));
+        assert_eq!(at(15.0), Some(Point::new(10.0, 0.0, -5.0)));
+        assert_eq!(at(15.1), None);
+        assert_eq!(at(-1.0), None);
+
+        assert_eq!(data.interpolate_along_path(&[], 0.0), None);
+        let single = vec![Rc::clone(&path[0])];
+        assert_eq!(
+            data.interpolate_along_path(&single, 0.0),
+            Some(Point::new(0.0, 0.0, 0.0))
+        );
+        assert_eq!(data.interpolate_along_path(&single, 1.0), None);
+    }
+
+    #[test]
+    fn test_leg_survey() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let station = |label| data.get_by_label(label).unwrap();
+
+        let from = station("nottsii.inlet5.inlet5-resurvey-4.22");
+        let to = station("nottsii.inlet5.inlet5-resurvey-4.26");
+        assert_eq!(
+            data.leg_survey(&from, &to),
+            Some(String::from("nottsii.inlet5.inlet5-resurvey-4"))
+        );
+
+        let to = station("nottsii.mainstreamway.mainstreamway3.27");
+        assert_eq!(data.leg_survey(&from, &to), Some(String::from("nottsii")));
+
+        let to = station("nottsii.entrance");
+        assert_eq!(data.leg_survey(&from, &to), Some(String::from("nottsii")));
+
+        let mut other = SurveyData::new();
+        let (a, _) = other.add_or_update(Point::new(0.0, 0.0, 0.0), "one.1");
+        let (b, _) = other.add_or_update(Point::new(1.0, 0.0, 0.0), "two.1");
+        assert_eq!(other.leg_survey(&a, &b), None);
+    }
+
+    #[test]
+    fn test_get_by_label_matches_scan() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        for station in &data.stations {
+            let label = station.borrow().label.clone();
+            let scanned = data
+                .stations
+                .iter()
+                .find(|s| s.borrow().label == label)
+                .unwrap();
+            let found = data.get_by_label(&label).unwrap();
+            assert!(Rc::ptr_eq(&found, scanned));
+        }
+        assert!(data.get_by_label("not.a.station").is_none());
+    }
+
+    #[test]
+    fn test_find_by_label_part() {
+        let mut data = SurveyData::new();
+        data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.10");
+        data.add_or_update(Point::new(2.0, 0.0, 0.0), "a.11");
+        data.add_or_update(Point::new(3.0, 0.0, 0.0), "b.2");
+
+        let LabelMatch::Exact(station) = data.find_by_label_part("a.1") else {
+            panic!("Expected an exact match");
+        };
+        assert_eq!(station.borrow().label, "a.1");
+        assert_eq!(data.get_by_label_part("a.1").unwrap().borrow().label, "a.1");
+
+        let LabelMatch::Unique(station) = data.find_by_label_part("b.") else {
+            panic!("Expected a unique match");
+        };
+        assert_eq!(station.borrow().label, "b.2");
+        assert_eq!(data.get_by_label_part("b.").unwrap().borrow().label, "b.2");
+
+        let LabelMatch::Ambiguous(stations) = data.find_by_label_part("a.") else {
+            panic!("Expected an ambiguous match");
+        };
+        let labels = stations
+            .iter()
+            .map(|station| station.borrow().label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["a.1", "a.10", "a.11"]);
+        assert!(data.get_by_label_part("a.").is_none());
+
+        assert!(matches!(data.find_by_label_part("c."), LabelMatch::None));
+        assert!(data.get_by_label_part("c.").is_none());
+    }
+
+    #[test]
+    fn test_get_by_index() {
+        let mut data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        for index in data.graph.node_indices() {
+            let station = data.get_by_index(index).unwrap();
+            assert_eq!(station.borrow().index, index);
+            assert_eq!(station.borrow().label, data.graph[index]);
+        }
+        assert!(data
+            .get_by_index(NodeIndex::new(data.stations.len()))
+            .is_none());
+
+        // Stations can still be found if the vector is reordered.
+        data.stations.reverse();
+        let index = NodeIndex::new(10);
+        assert_eq!(data.get_by_index(index).unwrap().borrow().index, index);
+    }
+
+    #[test]
+    fn test_relabel() {
+        let mut data = SurveyData::new();
+        let (station, index) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        data.relabel(index, "a.2");
+
+        assert!(data.get_by_label("a.1").is_none());
+        assert!(Rc::ptr_eq(&data.get_by_label("a.2").unwrap(), &station));
+        assert_eq!(station.borrow().label, "a.2");
+        assert_eq!(data.graph[index], "a.2");
+    }
+
+    #[test]
+    fn test_depth_below_entrance() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let label = "nottsii.mainstreamway.dome-jimlet.1";
+
+        // Nearest to the Voldemort entrance, at 350.84m.
+        let depth = data.depth_below_entrance(label).unwrap();
+        assert!((depth - (350.84 - 259.76)).abs() < 1e-9);
+        let depth = data
+            .depth_below_entrance_from(label, "nottsii.entrance")
+            .unwrap();
+        assert!((depth - (319.0 - 259.76)).abs() < 1e-9);
+        assert_eq!(data.depth_below_entrance("nottsii.entrance"), Some(0.0));
+
+        assert!(data.depth_below_entrance("nottsii.missing").is_none());
+        assert!(data
+            .depth_below_entrance_from(label, "nottsii.missing")
+            .is_none());
+
+        let mut data = SurveyData::new();
+        data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        assert!(data.depth_below_entrance("a.1").is_none());
+    }
+
+    #[test]
+    fn test_loops() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let loops = data.loops();
+        let self_loops = data
+            .graph
+            .edge_references()
+            .filter(|edge| edge.source() == edge.target())
+            .count();
+        assert_eq!(
+            loops.len(),
+            data.graph.edge_count() - self_loops + data.component_count() - data.graph.node_count()
+        );
+        assert!(!loops.is_empty());
+
+        for stations in &loops {
+            let indices = stations
+                .iter()
+                .map(|station| station.borrow().index)
+                .collect::<Vec<_>>();
+            let unique = indices.iter().collect::<HashSet<_>>();
+            assert_eq!(unique.len(), indices.len());
+            for i in 0..indices.len() {
+                let next = indices[(i + 1) % indices.len()];
+                assert!(data.graph.contains_edge(indices[i], next));
+            }
+        }
+
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        let (_, c) = data.add_or_update(Point::new(1.0, 1.0, 0.0), "a.3");
+        data.graph.add_edge(a, b, Leg::new(1.0));
+        assert!(data.loops().is_empty());
+        data.graph.add_edge(b, c, Leg::new(1.0));
+        data.graph.add_edge(c, a, Leg::new(1.4));
+        let loops = data.loops();
+        assert_eq!(loops.len(), 1);
+        let labels = loops[0]
+            .iter()
+            .map(|station| station.borrow().label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["a.2", "a.1", "a.3"]);
+    }
+
+    #[test]
+    fn test_connectivity_histogram() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let histogram = data.connectivity_histogram();
+        assert_eq!(histogram.values().sum::<usize>(), data.stations.len());
+        assert_eq!(histogram.get(&1), Some(&data.dead_ends().len()));
+        assert_eq!(
+            histogram.range(3..).map(|(_, count)| count).sum::<usize>(),
+            data.junctions().len()
+        );
+        assert_eq!(histogram.get(&0), Some(&data.isolated_stations().len()));
+
+        let most_connected = data.most_connected(5);
+        assert_eq!(most_connected.len(), 5);
+        let degrees = most_connected
+            .iter()
+            .map(|station| data.degree(&station.borrow().label).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(degrees[0], *histogram.keys().last().unwrap());
+        assert!(degrees.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(data.most_connected(usize::MAX).len(), data.stations.len());
+    }
+
+    #[test]
+    fn test_recompute_weights() {
+        // A direct route up a 20m pitch, and a longer route up a gentle slope.
+        let mut data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.2", Point::new(0.0, 0.0, 20.0), StationFlags::empty())
+            .add_station("a.3", Point::new(30.0, 0.0, 10.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .add_leg("a.1", "a.3")
+            .add_leg("a.3", "a.2")
+            .build()
+            .unwrap();
+        let labels = |path: Vec<RefStation>| {
+            path.iter()
+                .map(|station| station.borrow().label.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let (length, path) = data.shortest_path("a.1", "a.2").unwrap();
+        assert_eq!(length, 20.0);
+        assert_eq!(labels(path), vec!["a.1", "a.2"]);
+
+        // Penalise each climb by the square of its height, so that a single large climb costs
+        // more than several smaller ones.
+        data.recompute_weights(|a, b| a.horizontal_distance(b) + a.vertical_distance(b).powi(2));
+        let (length, path) = data.shortest_path("a.1", "a.2").unwrap();
+        assert_eq!(length, 260.0);
+        assert_eq!(labels(path), vec!["a.1", "a.3", "a.2"]);
+
+        data.recompute_weights(|a, b| a.distance(b));
+        assert_eq!(data.shortest_path("a.1", "a.2").unwrap().0, 20.0);
+    }
+
+    #[test]
+    fn test_nearest_leg() {
+        let mut data = SurveyData::new();
+        assert!(data.nearest_leg(&Point::new(0.0, 0.0, 0.0)).is_none());
+
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(10.0, 0.0, 0.0), "a.2");
+        let (_, c) = data.add_or_update(Point::new(10.0, 10.0, 0.0), "a.3");
+        data.graph.add_edge(a, b, Leg::new(10.0));
+        data.graph.add_edge(b, c, Leg::new(10.0));
+
+        // Beside the middle of the first leg, and nearer to the second station than the first,
+        // but still nearest to the first leg.
+        let (from, to, distance) = data.nearest_leg(&Point::new(7.0, -2.0, 0.0)).unwrap();
+        assert_eq!(from.borrow().label, "a.1");
+        assert_eq!(to.borrow().label, "a.2");
+        assert_eq!(distance, 2.0);
+
+        let (from, to, distance) = data.nearest_leg(&Point::new(11.5, 8.0, 0.0)).unwrap();
+        assert_eq!(from.borrow().label, "a.2");
+        assert_eq!(to.borrow().label, "a.3");
+        assert_eq!(distance, 1.5);
+    }
+
+    #[test]
+    fn test_nearest_station() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let entrance = data.get_by_label("nottsii.entrance").unwrap();
+        let p = Point::new(66667.98, 78303.03, 319.01);
+        let station = data.nearest_station(&p).unwrap();
+        // The entrance is equated with the top of the entrance shaft, so either may be returned
+        assert_eq!(station.borrow().coords, entrance.borrow().coords);
+
+        for station in data.stations.iter().step_by(97) {
+            let coords = station.borrow().coords;
+            let p = Point::new(coords.x + 0.01, coords.y - 0.02, coords.z);
+            let nearest = data.nearest_station(&p).unwrap();
+            let closest = data
+                .stations
+                .iter()
+                .map(|s| s.borrow().coords.distance(&p))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(nearest.borrow().coords.distance(&p), closest);
+        }
+    }
+
+    #[test]
+    fn test_stations_within() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let p = Point::new(66668.0, 78303.0, 319.0);
+        let within = data.stations_within(&p, 25.0);
+        let expected = data
+            .stations
+            .iter()
+            .filter(|s| s.borrow().coords.distance(&p) <= 25.0)
+            .collect::<Vec<_>>();
+        assert_eq!(within.len(), expected.len());
+        for (a, b) in within.iter().zip(expected) {
+            assert!(Rc::ptr_eq(a, b));
+        }
+    }
+
+    #[test]
+    fn test_cached_total_length_and_bounding_box() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let length = data.total_length(true, true);
+        let underground = data.total_length(false, true);
+        let bounding_box = data.bounding_box();
+        for _ in 0..3 {
+            assert_eq!(data.total_length(true, true), length);
+            assert_eq!(data.total_length(false, true), underground);
+            assert_eq!(data.bounding_box(), bounding_box);
+        }
+
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(3.0, 4.0, 0.0), "a.2");
+        data.graph.add_edge(a, b, Leg::new(5.0));
+        assert_eq!(data.total_length(true, true), 5.0);
+        assert_eq!(data.bounding_box().unwrap().max, Point::new(3.0, 4.0, 0.0));
+
+        data.add_or_update(Point::new(6.0, 8.0, 0.0), "a.2");
+        assert_eq!(data.bounding_box().unwrap().max, Point::new(6.0, 8.0, 0.0));
+        data.recompute_weights(|a, b| a.distance(b));
+        assert_eq!(data.total_length(true, true), 10.0);
+        data.graph.add_edge(a, b, Leg::new(1.0));
+        assert_eq!(data.total_length(true, true), 11.0);
+    }
+
+    #[test]
+    fn test_spatial_index_is_rebuilt() {
+        let mut data = SurveyData::new();
+        assert!(data.nearest_station(&Point::new(0.0, 0.0, 0.0)).is_none());
+
+        data.add_or_update(Point::new(10.0, 0.0, 0.0), "a.1");
+        let p = Point::new(0.0, 0.0, 0.0);
+        assert_eq!(data.nearest_station(&p).unwrap().borrow().label, "a.1");
+
+        data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        assert_eq!(data.nearest_station(&p).unwrap().borrow().label, "a.2");
+
+        data.add_or_update(Point::new(20.0, 0.0, 0.0), "a.2");
+        assert_eq!(data.nearest_station(&p).unwrap().borrow().label, "a.1");
+    }
+
+    #[test]
+    fn test_iter_stations() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert_eq!(data.iter_stations().count(), 1904);
+        for (station, label) in data.iter_stations().zip(data.labels()) {
+            assert_eq!(station.label, label);
+        }
+        assert!(data.labels().any(|label| label == "nottsii.entrance"));
+    }
+
+    #[test]
+    fn test_get_by_coords_tol() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let p = Point::new(66668.0 + 1e-9, 78303.0, 319.0);
+        assert!(data.get_by_coords(&p).is_none());
+        assert!(data.get_by_coords_tol(&p, 0.0).is_none());
+
+        let station = data.get_by_coords_tol(&p, 1e-6).unwrap();
+        assert_eq!(station.borrow().coords, Point::new(66668.0, 78303.0, 319.0));
+    }
+
+    #[test]
+    fn test_total_length() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let legs = data.graph.edge_weights().collect::<Vec<_>>();
+        assert_eq!(legs.iter().filter(|leg| leg.flags.surface).count(), 15);
+        assert_eq!(legs.iter().filter(|leg| leg.flags.duplicate).count(), 290);
+        assert_eq!(legs.iter().filter(|leg| leg.flags.splay).count(), 1326);
+
+        let sum = |f: fn(&Leg) -> bool| -> f64 {
+            legs.iter().filter(|leg| f(leg)).map(|leg| leg.length).sum()
+        };
+        let all = data.total_length(true, true);
+        assert_eq!(all, sum(|_| true));
+        assert!(
+            (data.total_length(false, true) - (all - sum(|leg| leg.flags.surface))).abs() < 1e-6
+        );
+        assert!(
+            (data.total_length(true, false) - (all - sum(|leg| leg.flags.duplicate))).abs() < 1e-6
+        );
+        assert!(data.total_length(false, false) < data.total_length(false, true));
+    }
+
+    #[test]
+    fn test_total_length_underground() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        assert!((data.total_length(true, true) - 44211.57).abs() < 0.01);
+        assert!((data.total_length_underground() - 44028.94).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_vertical_range() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert_eq!(data.vertical_range(), Some((204.91, 350.84)));
+        assert!((data.depth() - 145.93).abs() < 1e-9);
+
+        assert_eq!(SurveyData::new().vertical_range(), None);
+        assert_eq!(SurveyData::new().depth(), 0.0);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        assert_eq!(SurveyData::new().bounding_box(), None);
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let bbox = data.bounding_box().unwrap();
+        assert_eq!((bbox.min.z, bbox.max.z), data.vertical_range().unwrap());
+        assert!((bbox.size().z - data.depth()).abs() < 1e-9);
+        for station in &data.stations {
+            let p = station.borrow().coords;
+            assert!(bbox.min.x <= p.x && p.x <= bbox.max.x);
+            assert!(bbox.min.y <= p.y && p.y <= bbox.max.y);
+        }
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let (length, path) = data
+            .shortest_path(
+                "nottsii.entrance",
+                "nottsii.mainstreamway.mainstreamway3.27",
+            )
+            .unwrap();
+        assert_eq!(path.first().unwrap().borrow().label, "nottsii.entrance");
+        assert_eq!(
+            path.last().unwrap().borrow().label,
+            "nottsii.mainstreamway.mainstreamway3.27"
+        );
+
+        let mut total = 0.0;
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0].borrow().index, pair[1].borrow().index);
+            let edge = data.graph.find_edge(a, b).unwrap();
+            total += data.graph[edge].length;
+        }
+        assert!((total - length).abs() < 1e-9);
+        assert!(data
+            .shortest_path("nottsii.entrance", "nottsii.missing")
+            .is_none());
+
+        let mut data = SurveyData::new();
+        let (_, a) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (_, b) = data.add_or_update(Point::new(1.0, 0.0, 0.0), "a.2");
+        data.add_or_update(Point::new(5.0, 0.0, 0.0), "b.1");
+        data.graph.add_edge(a, b, Leg::new(1.0));
+        assert_eq!(data.shortest_path("a.1", "a.2").unwrap().0, 1.0);
+        assert!(data.shortest_path("a.1", "b.1").is_none());
+    }
+
+    #[test]
+    fn test_connected_components() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let components = data.connected_components();
+        assert_eq!(components.len(), 133);
+        assert_eq!(data.component_count(), 133);
+        assert_eq!(components[0].len(), 1772);
+
+        // Every other component is a single station with no legs
+        let isolated = data.isolated_stations();
+        assert_eq!(isolated.len(), 132);
+        for component in &components {
+            let indices = component
+                .iter()
+                .map(|s| s.borrow().index)
+                .collect::<Vec<_>>();
+            assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let json = data.to_json().unwrap();
+        let loaded = SurveyData::from_json(&json).unwrap();
+
+        assert_eq!(loaded.stations.len(), data.stations.len());
+        assert_eq!(loaded.graph.node_count(), data.graph.node_count());
+        assert_eq!(loaded.graph.edge_count(), data.graph.edge_count());
+        assert_eq!(loaded.title, data.title);
+        assert_eq!(loaded.coordinate_system, data.coordinate_system);
+        assert_eq!(loaded.timestamp, data.timestamp);
+
+        for (a, b) in data.stations.iter().zip(&loaded.stations) {
+            assert_eq!(*a.borrow(), *b.borrow());
+            assert_eq!(loaded.graph[b.borrow().index], b.borrow().label);
+        }
+        for (a, b) in data
+            .graph
+            .edge_references()
+            .zip(loaded.graph.edge_references())
+        {
+            assert_eq!(
+                (a.source(), a.target(), a.weight()),
+                (b.source(), b.target(), b.weight())
+            );
+        }
+
+        assert!(SurveyData::from_json("{}").is_err());
+    }
+
+    #[cfg(feature = "proj")]
+    #[test]
+    fn test_to_lat_lon() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert!(data
+            .to_lat_lon(&Point::new(66668.0, 78303.0, 319.0))
+            .is_none());
+        assert!(data.stations_to_lat_lon().is_none());
+
+        // 0733.3d uses UTM zone 30N and is in Matienzo, Cantabria
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let (lat, lon) = data
+            .to_lat_lon(&Point::new(450774.61, 4792901.14, 299.43))
+            .unwrap();
+        assert!((lat - 43.2873).abs() < 1e-3);
+        assert!((lon + 3.6068).abs() < 1e-3);
+
+        let all = data.stations_to_lat_lon().unwrap();
+        assert_eq!(all.len(), data.stations.len());
+        for (lat, lon) in all {
+            assert!((43.0..43.5).contains(&lat));
+            assert!((-3.9..-3.3).contains(&lon));
+        }
+    }
+
+    #[test]
+    fn test_dead_ends_and_junctions() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert_eq!(data.degree("nottsii.entrance"), Some(1));
+        assert_eq!(
+            data.degree("nottsii.mainstreamway.mainstreamway3.27"),
+            Some(2)
+        );
+        assert_eq!(data.degree("nottsii.missing"), None);
+
+        let dead_ends = data.dead_ends();
+        assert_eq!(dead_ends.len(), 187);
+        for label in ["nottsii.entrance", "nottsii.inlet14.inlet14_1.tag"] {
+            assert!(dead_ends.iter().any(|s| s.borrow().label == label));
+        }
+
+        let junctions = data.junctions();
+        assert_eq!(junctions.len(), 142);
+        for station in junctions {
+            assert!(data.degree(&station.borrow().label).unwrap() >= 3);
+        }
+    }
+
+    #[test]
+    fn test_subsurvey() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let prefix = "nottsii.mainstreamway.";
+        let sub = data.subsurvey(prefix);
+        assert_eq!(sub.stations.len(), 171);
+        assert_eq!(sub.graph.edge_count(), 134);
+
+        for (i, station) in sub.stations.iter().enumerate() {
+            let station = station.borrow();
+            assert!(station.label.starts_with(prefix));
+            assert_eq!(station.index.index(), i);
+            assert_eq!(sub.graph[station.index], station.label);
+        }
+        for edge in sub.graph.edge_references() {
+            let from = &sub.graph[edge.source()];
+            let to = &sub.graph[edge.target()];
+            let from = data.get_by_label(from).unwrap().borrow().index;
+            let to = data.get_by_label(to).unwrap().borrow().index;
+            assert!(data.graph.find_edge(from, to).is_some());
+        }
+
+        assert_eq!(data.subsurvey("nomatch").stations.len(), 0);
+    }
+
+    #[test]
+    fn test_passage_volume() {
+        let mut data = SurveyData::new();
+        let (a, ai) = data.add_or_update(Point::new(0.0, 0.0, 0.0), "a.1");
+        let (b, bi) = data.add_or_update(Point::new(10.0, 0.0, 0.0), "a.2");
+        let (c, ci) = data.add_or_update(Point::new(20.0, 0.0, 0.0), "a.3");
+        a.borrow_mut().lrud = LRUD::new(1.0, 1.0, 1.0, 1.0);
+        b.borrow_mut().lrud = LRUD::new(2.0, 2.0, 1.0, 1.0);
+        c.borrow_mut().lrud = LRUD::new(1.0, -1.0, 1.0, 1.0);
+        data.graph.add_edge(ai, bi, Leg::new(10.0));
+        data.graph.add_edge(bi, ci, Leg::new(10.0));
+        assert_eq!(data.passage_volume(), 30.0);
+
+        let mut splay = Leg::new(5.0);
+        splay.flags.splay = true;
+        data.graph.add_edge(ai, bi, splay);
+        assert_eq!(data.passage_volume(), 30.0);
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert!(data.passage_volume() > 0.0);
     }
 }
@@ -0,0 +1,138 @@
+//! Error types returned when reading and writing Survex files and building survey data
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+
+/// Errors which can occur while reading a Survex file
+#[derive(Debug)]
+pub enum ReadError {
+    /// The path could not be converted into a form which can be passed to the Survex library,
+    /// such as a path which is not valid UTF-8.
+    InvalidPath(PathBuf),
+    /// The survey name could not be passed to the Survex library, such as a name containing a
+    /// NUL byte.
+    InvalidSurvey(String),
+    /// The file does not exist.
+    NotFound(PathBuf),
+    /// The file exists but could not be opened for reading, such as because of its permissions
+    /// or because it is a directory. The error reported by the operating system is included.
+    Unreadable(PathBuf, io::Error),
+    /// The file is not in a format which the Survex library recognises.
+    BadFormat,
+    /// The file was written by a newer version of Survex and its format is not supported.
+    TooNew,
+    /// The Survex library was unable to open the file for another reason.
+    OpenFailed,
+    /// The Survex library encountered bad data while reading the file.
+    BadData,
+    /// The Survex library returned an item of an unrecognised type.
+    UnknownItem(i32),
+    /// A station referred to by the file could not be found. The station is identified by its
+    /// label or, if it is referred to by position, by its coordinates.
+    MissingStation(String),
+    /// A station label in the file is not valid UTF-8. The raw bytes of the label are included.
+    InvalidLabel(Vec<u8>),
+    /// A file listed in a manifest can not be combined with the files before it, because the
+    /// given property, such as its coordinate system, is different.
+    Incompatible(PathBuf, String),
+    /// An I/O error occurred, such as while reading a manifest file.
+    Io(io::Error),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPath(path) => write!(f, "Invalid path to Survex file: {:?}", path),
+            Self::InvalidSurvey(survey) => write!(f, "Invalid survey name: {:?}", survey),
+            Self::NotFound(path) => write!(f, "Survex file not found: {:?}", path),
+            Self::Unreadable(path, err) => {
+                write!(f, "Could not read Survex file {:?}: {}", path, err)
+            }
+            Self::BadFormat => write!(f, "File is not in a recognised Survex format"),
+            Self::TooNew => write!(f, "Survex file format is too new to be read"),
+            Self::OpenFailed => write!(f, "Could not open Survex file"),
+            Self::BadData => write!(f, "Bad data in Survex file"),
+            Self::UnknownItem(item) => write!(f, "Unknown item type {} in Survex file", item),
+            Self::MissingStation(station) => write!(f, "Could not find station {}", station),
+            Self::InvalidLabel(label) => write!(
+                f,
+                "Station label is not valid UTF-8: {}",
+                String::from_utf8_lossy(label)
+            ),
+            Self::Incompatible(path, property) => write!(
+                f,
+                "Survex file {:?} has a different {} to the other files",
+                path, property
+            ),
+            Self::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Unreadable(_, err) | Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Errors which can occur while building survey data with a
+/// [`SurveyBuilder`][`crate::builder::SurveyBuilder`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A station was added with the same label as an existing station.
+    DuplicateStation(String),
+    /// A leg referred to a station which has not been added.
+    MissingStation(String),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateStation(station) => write!(f, "Station {} was added twice", station),
+            Self::MissingStation(station) => write!(f, "Could not find station {}", station),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Errors which can occur while writing a Survex file
+#[derive(Debug)]
+pub enum WriteError {
+    /// The path could not be converted into a form which can be passed to the Survex library,
+    /// such as a path which is not valid UTF-8.
+    InvalidPath(PathBuf),
+    /// A string could not be passed to the Survex library because it contains a NUL byte. The
+    /// string is included.
+    InvalidString(String),
+    /// The Survex library was unable to open the file for writing.
+    OpenFailed,
+    /// The Survex library reported an error while writing or closing the file.
+    WriteFailed,
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPath(path) => write!(f, "Invalid path to Survex file: {:?}", path),
+            Self::InvalidString(string) => {
+                write!(f, "String contains a NUL byte: {:?}", string)
+            }
+            Self::OpenFailed => write!(f, "Could not open Survex file for writing"),
+            Self::WriteFailed => write!(f, "Could not write Survex file"),
+        }
+    }
+}
+
+impl Error for WriteError {}
@@ -0,0 +1,1210 @@
+//! Functions for exporting survey data to other formats
+//!
+//! Every exporter in this module accepts an [`ExportOptions`] instance, which controls which
+//! stations and legs are included in the output and how their coordinates are written. Use
+//! [`ExportOptions::default`] to export everything with coordinates to two decimal places.
+
+use crate::data::{cross_section, RefStation, SurveyData};
+use crate::leg::Leg;
+use crate::point::Point;
+use crate::station::Station;
+use log::warn;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{EdgeReference, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Write as _};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// The size, in metres, used by [`to_obj`] in place of any missing LRUD measurement.
+pub const OBJ_DEFAULT_LRUD: f64 = 0.1;
+
+/// Options which control the behaviour of the exporters in this module
+///
+/// Options are configured using builder-style methods:
+///
+/// ```rust
+/// use survex_rs::export::ExportOptions;
+/// use survex_rs::point::Point;
+///
+/// let options = ExportOptions::default()
+///     .include_surface(false)
+///     .precision(3)
+///     .transform(|p| Point::new(p.x - 66000.0, p.y - 78000.0, p.z));
+/// ```
+#[derive(Clone)]
+pub struct ExportOptions {
+    include_surface: bool,
+    include_anonymous: bool,
+    precision: usize,
+    transform: Option<Rc<dyn Fn(Point) -> Point>>,
+}
+
+impl Default for ExportOptions {
+    /// Returns an [`ExportOptions`] instance which includes all stations and legs, writes
+    /// coordinates to two decimal places and does not transform coordinates.
+    fn default() -> Self {
+        Self {
+            include_surface: true,
+            include_anonymous: true,
+            precision: 2,
+            transform: None,
+        }
+    }
+}
+
+impl Debug for ExportOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExportOptions")
+            .field("include_surface", &self.include_surface)
+            .field("include_anonymous", &self.include_anonymous)
+            .field("precision", &self.precision)
+            .field("transform", &self.transform.is_some())
+            .finish()
+    }
+}
+
+impl ExportOptions {
+    /// Set whether surface stations, and legs which connect to them, are included.
+    pub fn include_surface(mut self, include: bool) -> Self {
+        self.include_surface = include;
+        self
+    }
+
+    /// Set whether anonymous stations, and legs which connect to them, are included.
+    pub fn include_anonymous(mut self, include: bool) -> Self {
+        self.include_anonymous = include;
+        self
+    }
+
+    /// Set the number of decimal places used when writing coordinates.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set a function which will be applied to every coordinate before it is written, such as a
+    /// translation to a local origin or a reprojection.
+    pub fn transform(mut self, transform: impl Fn(Point) -> Point + 'static) -> Self {
+        self.transform = Some(Rc::new(transform));
+        self
+    }
+
+    /// Check whether the given [`Station`] should be included in the output.
+    pub fn includes_station(&self, station: &Station) -> bool {
+        (self.include_surface || !station.surface())
+            && (self.include_anonymous || !station.anonymous())
+    }
+
+    /// Check whether a [`Leg`] between the two given stations should be included in the output.
+    /// A leg is only included if both of its stations are included, and surface legs are
+    /// excluded along with surface stations.
+    pub fn includes_leg(&self, from: &Station, to: &Station, leg: &Leg) -> bool {
+        (self.include_surface || !leg.flags.surface)
+            && self.includes_station(from)
+            && self.includes_station(to)
+    }
+
+    /// Apply the coordinate transform, if any, to the given [`Point`].
+    pub fn apply(&self, point: Point) -> Point {
+        match &self.transform {
+            Some(transform) => transform(point),
+            None => point,
+        }
+    }
+
+    /// Format a single coordinate value using the configured precision.
+    pub fn format_coord(&self, value: f64) -> String {
+        format!("{:.*}", self.precision, value)
+    }
+}
+
+/// Export the stations and legs of a survey as a GeoJSON `FeatureCollection`, such as for
+/// displaying a survey with Leaflet or Mapbox.
+///
+/// Stations are written as `Point` features with their label and flags as properties, and legs
+/// are written as `LineString` features with the labels of their stations, their length and the
+/// survey they belong to (see [`SurveyData::leg_survey`]) as properties. Coordinates are written
+/// as `[x, y, z]` in the coordinate system of the survey, after applying any transform set in
+/// `options`. If the survey has a coordinate system, it is included as a top-level `crs` member.
+pub fn to_geojson(data: &SurveyData, options: &ExportOptions) -> String {
+    let mut features = Vec::new();
+
+    for station in &data.stations {
+        let station = station.borrow();
+        if !options.includes_station(&station) {
+            continue;
+        }
+        features.push(format!(
+            "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\"coordinates\":{}}},\
+             \"properties\":{{\"label\":{},\"surface\":{},\"underground\":{},\"entrance\":{},\
+             \"exported\":{},\"fixed\":{},\"anonymous\":{},\"wall\":{}}}}}",
+            geojson_position(station.coords, options),
+            json_string(&station.label),
+            station.surface(),
+            station.underground(),
+            station.entrance(),
+            station.exported(),
+            station.fixed(),
+            station.anonymous(),
+            station.wall(),
+        ));
+    }
+
+    for (from, to, leg) in included_legs(data, options) {
+        let survey = match data.leg_survey(&from, &to) {
+            Some(survey) => json_string(&survey),
+            None => String::from("null"),
+        };
+        let (from, to) = (from.borrow(), to.borrow());
+        features.push(format!(
+            "{{\"type\":\"Feature\",\
+             \"geometry\":{{\"type\":\"LineString\",\"coordinates\":[{},{}]}},\
+             \"properties\":{{\"from\":{},\"to\":{},\"length\":{},\"survey\":{}}}}}",
+            geojson_position(from.coords, options),
+            geojson_position(to.coords, options),
+            json_string(&from.label),
+            json_string(&to.label),
+            options.format_coord(leg.length),
+            survey,
+        ));
+    }
+
+    let mut geojson = String::from("{\"type\":\"FeatureCollection\",");
+    if let Some(cs) = data.coordinate_system() {
+        write!(
+            geojson,
+            "\"crs\":{{\"type\":\"name\",\"properties\":{{\"name\":{}}}}},",
+            json_string(cs)
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    write!(geojson, "\"features\":[\n{}\n]}}\n", features.join(",\n"))
+        .expect("Writing to a String cannot fail");
+    geojson
+}
+
+/// Export the stations of a survey as a CSV table, such as for use in a spreadsheet.
+///
+/// The first row is a header, followed by one row per station with the columns `label`, `x`,
+/// `y`, `z`, `left`, `right`, `up`, `down`, `surface`, `underground`, `entrance`, `exported`,
+/// `fixed`, `anonymous` and `wall`. Missing LRUD measurements are written as empty cells and flags
+/// are written as `true` or `false`.
+pub fn stations_to_csv(
+    data: &SurveyData,
+    options: &ExportOptions,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "label,x,y,z,left,right,up,down,surface,underground,entrance,exported,fixed,anonymous,wall"
+    )?;
+
+    let lrud = |value: Option<f64>| value.map_or(String::new(), |v| options.format_coord(v));
+    for station in &data.stations {
+        let station = station.borrow();
+        if !options.includes_station(&station) {
+            continue;
+        }
+        let coords = options.apply(station.coords);
+        let measurements = station.lrud.convert(data.units);
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&station.label),
+            options.format_coord(coords.x),
+            options.format_coord(coords.y),
+            options.format_coord(coords.z),
+            lrud(measurements.left),
+            lrud(measurements.right),
+            lrud(measurements.up),
+            lrud(measurements.down),
+            station.surface(),
+            station.underground(),
+            station.entrance(),
+            station.exported(),
+            station.fixed(),
+            station.anonymous(),
+            station.wall(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Export the legs of a survey as GPX tracks, such as for viewing a cave centreline alongside
+/// surface GPS tracks. This requires the `proj` feature.
+///
+/// GPX coordinates are WGS84 latitude and longitude, so the survey must have a
+/// [coordinate system][`SurveyData::coordinate_system`] which PROJ understands (such as
+/// `EPSG:32630`), and the x and y coordinates of each station are reprojected from it after
+/// applying any transform set in `options`. The z coordinate is written unchanged as the
+/// elevation, so it is assumed to be a height in metres. Latitude and longitude are always written
+/// to seven decimal places, while `options` controls the precision of the elevation.
+///
+/// Each connected component of the survey is written as a `<trk>`, with each of its legs as a
+/// `<trkseg>` of two points. Returns [`None`] if the survey has no coordinate system or the
+/// coordinates could not be reprojected.
+#[cfg(feature = "proj")]
+pub fn to_gpx(data: &SurveyData, options: &ExportOptions) -> Option<String> {
+    let proj = data.lat_lon_transform()?;
+
+    let components = data.connected_components();
+    let mut component_of = HashMap::new();
+    for (i, component) in components.iter().enumerate() {
+        for station in component {
+            component_of.insert(station.borrow().index, i);
+        }
+    }
+    let mut tracks = vec![Vec::new(); components.len()];
+    for (from, to, _) in included_legs(data, options) {
+        let index = from.borrow().index;
+        if let Some(&i) = component_of.get(&index) {
+            tracks[i].push((from, to));
+        }
+    }
+
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"survex-rs\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    for track in tracks.iter().filter(|track| !track.is_empty()) {
+        writeln!(gpx, "<trk><name>{}</name>", xml_escape(&data.title))
+            .expect("Writing to a String cannot fail");
+        for (from, to) in track {
+            writeln!(
+                gpx,
+                "<trkseg>{}{}</trkseg>",
+                gpx_point(&proj, from.borrow().coords, options)?,
+                gpx_point(&proj, to.borrow().coords, options)?
+            )
+            .expect("Writing to a String cannot fail");
+        }
+        gpx.push_str("</trk>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    Some(gpx)
+}
+
+/// Format a [`Point`] as a GPX `<trkpt>`, reprojecting it to latitude and longitude using the
+/// given transformation. Returns [`None`] if the point could not be reprojected.
+#[cfg(feature = "proj")]
+fn gpx_point(proj: &proj::Proj, point: Point, options: &ExportOptions) -> Option<String> {
+    let point = options.apply(point);
+    let (lon, lat) = proj.convert((point.x, point.y)).ok()?;
+    Some(format!(
+        "<trkpt lat=\"{:.7}\" lon=\"{:.7}\"><ele>{}</ele></trkpt>",
+        lat,
+        lon,
+        options.format_coord(point.z)
+    ))
+}
+
+/// Export the stations and legs of a survey as a KML document, such as for viewing a survey in
+/// Google Earth.
+///
+/// Stations are written as `<Placemark>` points named with their labels, with entrances given a
+/// red icon and all other stations a yellow icon. Legs are written as `<LineString>` paths of two
+/// points. All coordinates use the `absolute` altitude mode, so the z coordinate of each station
+/// is treated as its height above sea level in metres.
+///
+/// KML coordinates are WGS84 longitude and latitude. With the `proj` feature enabled, the x and y
+/// coordinates of each station are reprojected from the
+/// [coordinate system][`SurveyData::coordinate_system`] of the survey after applying any
+/// transform set in `options`, and written to seven decimal places. If the `proj` feature is
+/// disabled, the survey has no coordinate system, or the coordinate system cannot be used, a
+/// warning is logged and the coordinates are written unchanged, which Google Earth will not
+/// place correctly.
+pub fn to_kml(data: &SurveyData, options: &ExportOptions) -> String {
+    #[cfg(feature = "proj")]
+    let proj = data.lat_lon_transform();
+    #[cfg(not(feature = "proj"))]
+    let proj: Option<()> = None;
+    if proj.is_none() {
+        warn!("Could not reproject survey to WGS84 for KML export. Writing raw coordinates.");
+    }
+
+    let position = |point: Point| {
+        let point = options.apply(point);
+        #[cfg(feature = "proj")]
+        if let Some((lon, lat)) = proj
+            .as_ref()
+            .and_then(|proj| proj.convert((point.x, point.y)).ok())
+        {
+            return format!("{:.7},{:.7},{}", lon, lat, options.format_coord(point.z));
+        }
+        format!(
+            "{},{},{}",
+            options.format_coord(point.x),
+            options.format_coord(point.y),
+            options.format_coord(point.z)
+        )
+    };
+
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n",
+    );
+    writeln!(kml, "<name>{}</name>", xml_escape(&data.title))
+        .expect("Writing to a String cannot fail");
+    kml.push_str(
+        "<Style id=\"station\"><IconStyle><color>ff00ffff</color></IconStyle></Style>\n\
+         <Style id=\"entrance\"><IconStyle><color>ff0000ff</color></IconStyle></Style>\n\
+         <Style id=\"leg\"><LineStyle><color>ff00ffff</color><width>2</width></LineStyle></Style>\n",
+    );
+
+    kml.push_str("<Folder>\n<name>Stations</name>\n");
+    for station in data.iter_stations() {
+        if !options.includes_station(&station) {
+            continue;
+        }
+        let style = if station.entrance() {
+            "entrance"
+        } else {
+            "station"
+        };
+        writeln!(
+            kml,
+            "<Placemark><name>{}</name><styleUrl>#{}</styleUrl><Point>\
+             <altitudeMode>absolute</altitudeMode><coordinates>{}</coordinates></Point></Placemark>",
+            xml_escape(&station.label),
+            style,
+            position(station.coords)
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    kml.push_str("</Folder>\n");
+
+    kml.push_str("<Folder>\n<name>Legs</name>\n");
+    for (from, to, _) in included_legs(data, options) {
+        writeln!(
+            kml,
+            "<Placemark><styleUrl>#leg</styleUrl><LineString>\
+             <altitudeMode>absolute</altitudeMode><coordinates>{} {}</coordinates>\
+             </LineString></Placemark>",
+            position(from.borrow().coords),
+            position(to.borrow().coords)
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    kml.push_str("</Folder>\n</Document>\n</kml>\n");
+    kml
+}
+
+/// Export a survey as an ASCII PLY file, such as for loading into MeshLab or CloudCompare.
+///
+/// Each station is written as a vertex with `x`, `y` and `z` properties, and each leg is written
+/// as an edge between the vertices of its two stations. Tools which only handle point clouds will
+/// ignore the edges.
+pub fn to_ply(
+    data: &SurveyData,
+    options: &ExportOptions,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let mut vertices = Vec::new();
+    let mut vertex_of = HashMap::new();
+    for station in &data.stations {
+        let station = station.borrow();
+        if options.includes_station(&station) {
+            vertex_of.insert(station.index, vertices.len());
+            vertices.push(options.apply(station.coords));
+        }
+    }
+    let edges = included_legs(data, options)
+        .into_iter()
+        .filter_map(|(from, to, _)| {
+            let from = vertex_of.get(&from.borrow().index)?;
+            let to = vertex_of.get(&to.borrow().index)?;
+            Some((*from, *to))
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format ascii 1.0")?;
+    writeln!(writer, "comment {}", single_line(&data.title))?;
+    writeln!(writer, "element vertex {}", vertices.len())?;
+    writeln!(writer, "property double x")?;
+    writeln!(writer, "property double y")?;
+    writeln!(writer, "property double z")?;
+    writeln!(writer, "element edge {}", edges.len())?;
+    writeln!(writer, "property int vertex1")?;
+    writeln!(writer, "property int vertex2")?;
+    writeln!(writer, "end_header")?;
+    for vertex in vertices {
+        writeln!(
+            writer,
+            "{} {} {}",
+            options.format_coord(vertex.x),
+            options.format_coord(vertex.y),
+            options.format_coord(vertex.z)
+        )?;
+    }
+    for (from, to) in edges {
+        writeln!(writer, "{} {}", from, to)?;
+    }
+    Ok(())
+}
+
+/// Export the passages of a survey as a Wavefront OBJ mesh, such as for rendering or 3D printing.
+///
+/// Each leg is written as a separate tube with a rectangular cross-section at each of its two
+/// stations, sized by the [`LRUD`][`crate::station::LRUD`] measurements of that station. Any
+/// missing measurement is replaced by [`OBJ_DEFAULT_LRUD`], so legs without LRUD data become thin
+/// tubes. Splay legs are not written, as they do not represent passage.
+///
+/// The cross-sections are always vertical. Up and down are measured along the z axis, and left
+/// and right are measured horizontally at right angles to the leg, as seen when looking from the
+/// leg's first station towards its second. Vertical legs use the x axis for left and right.
+///
+/// Each tube is written as eight vertices, four at each end ordered top left, top right, bottom
+/// right and bottom left, followed by six quadrilateral faces: four sides and a cap at each end.
+/// Faces are wound anticlockwise when viewed from outside the tube. Transforms set in `options`
+/// are applied to station coordinates before the cross-sections are added.
+pub fn to_obj(
+    data: &SurveyData,
+    options: &ExportOptions,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(writer, "# {}", single_line(&data.title))?;
+    let mut tubes = 0;
+    for (from, to, leg) in included_legs(data, options) {
+        if leg.flags.splay {
+            continue;
+        }
+        let (from, to) = (from.borrow(), to.borrow());
+        let (start, end) = (options.apply(from.coords), options.apply(to.coords));
+        let direction = end - start;
+
+        for (point, lrud) in [(start, from.lrud), (end, to.lrud)] {
+            let lrud = lrud.convert(data.units);
+            let measurements = [lrud.left, lrud.right, lrud.up, lrud.down]
+                .map(|value| value.unwrap_or(OBJ_DEFAULT_LRUD));
+            for vertex in cross_section(point, direction, measurements) {
+                writeln!(
+                    writer,
+                    "v {} {} {}",
+                    options.format_coord(vertex.x),
+                    options.format_coord(vertex.y),
+                    options.format_coord(vertex.z)
+                )?;
+            }
+        }
+
+        // OBJ vertex indices start at 1. The start cross-section is s..s+3 and the end
+        // cross-section is s+4..s+7.
+        let s = tubes * 8 + 1;
+        for i in 0..4 {
+            let next = (i + 1) % 4;
+            writeln!(
+                writer,
+                "f {} {} {} {}",
+                s + i,
+                s + next,
+                s + 4 + next,
+                s + 4 + i
+            )?;
+        }
+        writeln!(writer, "f {} {} {} {}", s + 3, s + 2, s + 1, s)?;
+        writeln!(writer, "f {} {} {} {}", s + 4, s + 5, s + 6, s + 7)?;
+        tubes += 1;
+    }
+    Ok(())
+}
+
+/// Export a survey as a Therion `.th` file, such as for drawing a survey in Therion using data
+/// processed by Survex.
+///
+/// The output is a single `survey` block named after the title of the survey, containing a
+/// `centreline` block with a `fix` for every station at its coordinates and a `data nosurvey`
+/// table with the two stations of every leg. Entrance stations are marked with the `entrance`
+/// station flag. If the coordinate system of the survey is an EPSG code, such as `EPSG:27700`,
+/// it is written as the `cs` of the centreline. Coordinates are written using the precision and
+/// transform set in `options`.
+///
+/// Only the shape of the survey can be read back from the output. Station positions, the
+/// connections between stations, the title and the entrance flag are kept, but every station
+/// becomes a fixed point and legs have no measurements, so Therion will not close loops or
+/// calculate errors. LRUDs, leg styles and dates, loop errors and every other station flag are
+/// not written. Station labels are kept as a flat list within the one survey rather than being
+/// split into Therion's survey hierarchy, and any character which Therion does not accept in a
+/// station name is replaced with an underscore.
+pub fn to_therion(
+    data: &SurveyData,
+    options: &ExportOptions,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(writer, "encoding utf-8")?;
+    let name = if data.title.is_empty() {
+        String::from("survey")
+    } else {
+        therion_name(&data.title)
+    };
+    writeln!(
+        writer,
+        "survey {} -title \"{}\"",
+        name,
+        single_line(&data.title).replace('"', "\"\"")
+    )?;
+    writeln!(writer, "  centreline")?;
+    if let Some(cs) = data
+        .coordinate_system
+        .as_deref()
+        .filter(|cs| cs.starts_with("EPSG:"))
+    {
+        writeln!(writer, "    cs {}", cs)?;
+    }
+
+    let mut entrances = Vec::new();
+    for station in &data.stations {
+        let station = station.borrow();
+        if !options.includes_station(&station) {
+            continue;
+        }
+        let coords = options.apply(station.coords);
+        writeln!(
+            writer,
+            "    fix {} {} {} {}",
+            therion_name(&station.label),
+            options.format_coord(coords.x),
+            options.format_coord(coords.y),
+            options.format_coord(coords.z)
+        )?;
+        if station.entrance() {
+            entrances.push(therion_name(&station.label));
+        }
+    }
+
+    let legs = included_legs(data, options);
+    if !legs.is_empty() {
+        writeln!(writer, "    data nosurvey from to")?;
+        for (from, to, _) in legs {
+            writeln!(
+                writer,
+                "    {} {}",
+                therion_name(&from.borrow().label),
+                therion_name(&to.borrow().label)
+            )?;
+        }
+    }
+
+    for entrance in entrances {
+        writeln!(writer, "    station {} \"\" entrance", entrance)?;
+    }
+    writeln!(writer, "  endcentreline")?;
+    writeln!(writer, "endsurvey")?;
+    Ok(())
+}
+
+/// Export the graph of a survey in the GraphViz DOT format, such as for visualising the
+/// topology of a survey while debugging.
+///
+/// Nodes are labelled with station labels and edges with leg lengths, written using the precision
+/// set in `options`. Entrance stations are drawn as double circles.
+pub fn to_dot(data: &SurveyData, options: &ExportOptions) -> String {
+    let graph = data.graph.filter_map(
+        |index, _| {
+            let station = data.get_by_index(index)?;
+            let included = options.includes_station(&station.borrow());
+            included.then_some(station)
+        },
+        |edge, leg| {
+            let (from, to) = data.graph.edge_endpoints(edge)?;
+            let (from, to) = (data.get_by_index(from)?, data.get_by_index(to)?);
+            let included = options.includes_leg(&from.borrow(), &to.borrow(), leg);
+            included.then_some(*leg)
+        },
+    );
+
+    let edge_attributes = |_, edge: EdgeReference<Leg>| {
+        format!("label = \"{}\"", options.format_coord(edge.weight().length))
+    };
+    let node_attributes = |_, (_, station): (NodeIndex, &RefStation)| {
+        let station = station.borrow();
+        let label = format!("label = \"{}\"", station.label.replace('"', "\\\""));
+        if station.entrance() {
+            format!("{}, shape = doublecircle", label)
+        } else {
+            label
+        }
+    };
+    let dot = Dot::with_attr_getters(
+        &graph,
+        &[Config::NodeNoLabel, Config::EdgeNoLabel],
+        &edge_attributes,
+        &node_attributes,
+    );
+    format!("{:?}", dot)
+}
+
+/// Export the graph of a survey as a GraphML document, such as for analysing the topology of a
+/// survey in Gephi or Cytoscape.
+///
+/// Each station is written as a `<node>` with its label, coordinates and flags as attributes, and
+/// each leg as an `<edge>` with its length as an attribute. Node IDs are formed from the index of
+/// the station in the graph, such as `n42`, and edge IDs from the index of the leg, such as
+/// `e7`, so they are the same each time a survey is exported. Coordinates and lengths are written
+/// using the precision and transform set in `options`.
+pub fn to_graphml(data: &SurveyData, options: &ExportOptions) -> String {
+    let mut graphml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n\
+         <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+    );
+    for axis in ["x", "y", "z"] {
+        writeln!(
+            graphml,
+            "<key id=\"{0}\" for=\"node\" attr.name=\"{0}\" attr.type=\"double\"/>",
+            axis
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    for flag in GRAPHML_FLAGS {
+        writeln!(
+            graphml,
+            "<key id=\"{0}\" for=\"node\" attr.name=\"{0}\" attr.type=\"boolean\"/>",
+            flag
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    graphml.push_str(
+        "<key id=\"length\" for=\"edge\" attr.name=\"length\" attr.type=\"double\"/>\n\
+         <graph id=\"G\" edgedefault=\"undirected\">\n",
+    );
+    writeln!(graphml, "<desc>{}</desc>", xml_escape(&data.title))
+        .expect("Writing to a String cannot fail");
+
+    for station in data.iter_stations() {
+        if !options.includes_station(&station) {
+            continue;
+        }
+        let coords = options.apply(station.coords);
+        write!(
+            graphml,
+            "<node id=\"n{}\"><data key=\"label\">{}</data><data key=\"x\">{}</data>\
+             <data key=\"y\">{}</data><data key=\"z\">{}</data>",
+            station.index.index(),
+            xml_escape(&station.label),
+            options.format_coord(coords.x),
+            options.format_coord(coords.y),
+            options.format_coord(coords.z)
+        )
+        .expect("Writing to a String cannot fail");
+        let flags = [
+            station.surface(),
+            station.underground(),
+            station.entrance(),
+            station.exported(),
+            station.fixed(),
+            station.anonymous(),
+            station.wall(),
+        ];
+        for (flag, value) in GRAPHML_FLAGS.iter().zip(flags) {
+            write!(graphml, "<data key=\"{}\">{}</data>", flag, value)
+                .expect("Writing to a String cannot fail");
+        }
+        graphml.push_str("</node>\n");
+    }
+
+    for edge in data.graph.edge_references() {
+        let (Some(from), Some(to)) = (
+            data.get_by_index(edge.source()),
+            data.get_by_index(edge.target()),
+        ) else {
+            continue;
+        };
+        if !options.includes_leg(&from.borrow(), &to.borrow(), edge.weight()) {
+            continue;
+        }
+        writeln!(
+            graphml,
+            "<edge id=\"e{}\" source=\"n{}\" target=\"n{}\">\
+             <data key=\"length\">{}</data></edge>",
+            edge.id().index(),
+            edge.source().index(),
+            edge.target().index(),
+            options.format_coord(edge.weight().length)
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    graphml.push_str("</graph>\n</graphml>\n");
+    graphml
+}
+
+/// The station flags written as node attributes by [`to_graphml`], in the order they are written.
+const GRAPHML_FLAGS: [&str; 7] = [
+    "surface",
+    "underground",
+    "entrance",
+    "exported",
+    "fixed",
+    "anonymous",
+    "wall",
+];
+
+/// Retrieve the legs of a survey which should be included in the output, along with the stations
+/// at either end of each leg, in the order they appear in the graph.
+fn included_legs<'a>(
+    data: &'a SurveyData,
+    options: &ExportOptions,
+) -> Vec<(RefStation, RefStation, &'a Leg)> {
+    data.graph
+        .edge_references()
+        .filter_map(|edge| {
+            let from = data.get_by_index(edge.source())?;
+            let to = data.get_by_index(edge.target())?;
+            let included = options.includes_leg(&from.borrow(), &to.borrow(), edge.weight());
+            included.then_some((from, to, edge.weight()))
+        })
+        .collect()
+}
+
+/// Convert a label into a Therion name, which may contain letters, digits and the characters
+/// `_-/+*.,'`, but may not start with any of `-+*.,'`. Other characters are replaced with an
+/// underscore, and an underscore is added to the start of the name if required.
+fn therion_name(label: &str) -> String {
+    let mut name = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "_-/+*.,'".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.starts_with(['-', '+', '*', '.', ',', '\'']) || name.is_empty() {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Format a [`Point`] as a GeoJSON position of the form `[x,y,z]`.
+fn geojson_position(point: Point, options: &ExportOptions) -> String {
+    let point = options.apply(point);
+    format!(
+        "[{},{},{}]",
+        options.format_coord(point.x),
+        options.format_coord(point.y),
+        options.format_coord(point.z)
+    )
+}
+
+/// Format a string as a quoted JSON string, escaping any characters which JSON requires to be
+/// escaped.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(escaped, "\\u{:04x}", c as u32).expect("Writing to a String cannot fail")
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Replace any line breaks in a string with spaces, so that it can be written as a comment which
+/// must fit on a single line.
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+/// Escape the characters in a string which have a special meaning in XML.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a string as a CSV field, quoting it if it contains a comma, quote or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::SurveyBuilder;
+    use crate::read::load_from_path;
+    use crate::station::StationFlags;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_export_options() {
+        let mut station = Station::new(
+            String::from("a.1"),
+            Point::new(1.0, 2.0, 3.0),
+            NodeIndex::new(0),
+        );
+        let other = station.clone();
+        let leg = Leg::new(1.0);
+
+        let options = ExportOptions::default();
+        assert!(options.includes_station(&station));
+        assert_eq!(options.apply(station.coords), station.coords);
+        assert_eq!(options.format_coord(1.23456), "1.23");
+
+        station.flags.insert(StationFlags::SURFACE);
+        let options = ExportOptions::default()
+            .include_surface(false)
+            .precision(0)
+            .transform(|p| Point::new(p.x * 2.0, p.y, p.z));
+        assert!(!options.includes_station(&station));
+        assert!(!options.includes_leg(&station, &other, &leg));
+        assert!(options.includes_station(&other));
+        let mut surface_leg = leg;
+        surface_leg.flags.surface = true;
+        assert!(options.includes_leg(&other, &other, &leg));
+        assert!(!options.includes_leg(&other, &other, &surface_leg));
+        assert_eq!(options.apply(other.coords), Point::new(2.0, 2.0, 3.0));
+        assert_eq!(options.format_coord(1.5), "2");
+    }
+
+    #[test]
+    fn test_to_geojson() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let geojson = to_geojson(&data, &ExportOptions::default());
+        let value: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(value["type"], "FeatureCollection");
+        assert!(value.get("crs").is_none());
+
+        let features = value["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1904 + 1782);
+        let points = features
+            .iter()
+            .filter(|f| f["geometry"]["type"] == "Point")
+            .count();
+        assert_eq!(points, 1904);
+
+        let entrance = features
+            .iter()
+            .find(|f| f["properties"]["label"] == "nottsii.entrance")
+            .unwrap();
+        assert_eq!(
+            entrance["geometry"]["coordinates"],
+            serde_json::json!([66668.0, 78303.0, 319.0])
+        );
+        assert_eq!(entrance["properties"]["entrance"], true);
+
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let geojson = to_geojson(&data, &ExportOptions::default().include_surface(false));
+        let value: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(value["crs"]["properties"]["name"], "EPSG:32630");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a.1"), "\"a.1\"");
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_stations_to_csv() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let mut csv = Vec::new();
+        stations_to_csv(&data, &ExportOptions::default(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1 + 6104);
+        assert!(lines[0].starts_with("label,x,y,z,left,right,up,down,surface"));
+        assert!(lines.iter().all(|line| line.split(',').count() == 15));
+        // Stations without LRUD measurements have empty cells rather than placeholder values
+        assert!(lines[1..].iter().any(|line| line.contains(",,,,")));
+
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+    }
+
+    #[cfg(feature = "proj")]
+    #[test]
+    fn test_to_gpx() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert!(to_gpx(&data, &ExportOptions::default()).is_none());
+
+        // 0733.3d uses UTM zone 30N and is in Matienzo, Cantabria
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let gpx = to_gpx(&data, &ExportOptions::default()).unwrap();
+        assert!(gpx.starts_with("<?xml"));
+        assert!(gpx.trim_end().ends_with("</gpx>"));
+        assert!(gpx.contains("<trk><name>0733</name>"));
+
+        let points = gpx.matches("<trkpt ").count();
+        assert!(points > 0 && points % 2 == 0);
+        for point in gpx.split("<trkpt ").skip(1) {
+            let attr = |name: &str| -> f64 {
+                let start = point.find(&format!("{}=\"", name)).unwrap() + name.len() + 2;
+                let end = start + point[start..].find('"').unwrap();
+                point[start..end].parse().unwrap()
+            };
+            let (lat, lon) = (attr("lat"), attr("lon"));
+            assert!((43.0..43.5).contains(&lat), "Latitude {} out of range", lat);
+            assert!(
+                (-3.9..-3.3).contains(&lon),
+                "Longitude {} out of range",
+                lon
+            );
+        }
+    }
+
+    /// Check that every tag in an XML document is closed in the right order. This is not a full
+    /// XML parser, but is enough to catch mistakes in the exporters.
+    fn assert_balanced_xml(xml: &str) {
+        let mut open = Vec::new();
+        for tag in xml.split('<').skip(1) {
+            let tag = tag.split('>').next().unwrap();
+            if tag.starts_with('?') || tag.ends_with('/') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "Unexpected closing tag {}", name);
+            } else {
+                open.push(tag.split_whitespace().next().unwrap());
+            }
+        }
+        assert!(open.is_empty(), "Unclosed tags {:?}", open);
+    }
+
+    #[test]
+    fn test_to_kml() {
+        let mut data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        data.title = String::from("Notts II & friends");
+        let kml = to_kml(&data, &ExportOptions::default());
+
+        assert_balanced_xml(&kml);
+        assert!(kml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml "));
+        assert!(kml.contains("<name>Notts II &amp; friends</name>"));
+        assert_eq!(kml.matches("<Point>").count(), 1904);
+        assert_eq!(kml.matches("<LineString>").count(), 1782);
+        assert_eq!(kml.matches("<styleUrl>#entrance</styleUrl>").count(), 3);
+        assert_eq!(
+            kml.matches("<altitudeMode>absolute</altitudeMode>").count(),
+            1904 + 1782
+        );
+        #[cfg(not(feature = "proj"))]
+        assert!(kml.contains(
+            "<name>nottsii.entrance</name><styleUrl>#entrance</styleUrl><Point>\
+             <altitudeMode>absolute</altitudeMode><coordinates>66668.00,78303.00,319.00"
+        ));
+
+        // 0733.3d uses UTM zone 30N and is in Matienzo, Cantabria. KML puts longitude first.
+        #[cfg(feature = "proj")]
+        {
+            let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+            let kml = to_kml(&data, &ExportOptions::default());
+            assert_balanced_xml(&kml);
+            for coordinates in kml.split("<coordinates>").skip(1) {
+                let coordinates = coordinates.split("</coordinates>").next().unwrap();
+                for position in coordinates.split_whitespace() {
+                    let position = position
+                        .split(',')
+                        .map(|value| value.parse::<f64>().unwrap())
+                        .collect::<Vec<_>>();
+                    assert!((-3.9..-3.3).contains(&position[0]), "Bad longitude");
+                    assert!((43.0..43.5).contains(&position[1]), "Bad latitude");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_ply() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let mut ply = Vec::new();
+        to_ply(&data, &ExportOptions::default(), &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+        assert!(header.contains("element vertex 1904\n"));
+        assert!(header.contains("element edge 1782\n"));
+        assert_eq!(body.lines().count(), 1904 + 1782);
+        assert!(body.contains("66668.00 78303.00 319.00\n"));
+
+        let mut data = SurveyData::new();
+        data.title = String::from("Two\r\nlines");
+        let mut ply = Vec::new();
+        to_ply(&data, &ExportOptions::default(), &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        assert!(ply.contains("\ncomment Two  lines\nelement vertex 0\n"));
+    }
+
+    #[test]
+    fn test_to_obj() {
+        let data = SurveyBuilder::new()
+            .title("Tube")
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::ENTRANCE)
+            .add_station("a.2", Point::new(0.0, 10.0, 0.0), StationFlags::UNDERGROUND)
+            .add_station(
+                "a.3",
+                Point::new(0.0, 10.0, -5.0),
+                StationFlags::UNDERGROUND,
+            )
+            .add_leg("a.1", "a.2")
+            .add_leg("a.2", "a.3")
+            .build()
+            .unwrap();
+        data.get_by_label("a.1")
+            .unwrap()
+            .borrow_mut()
+            .lrud
+            .update(1.0, 2.0, 3.0, 4.0);
+
+        let mut obj = Vec::new();
+        to_obj(&data, &ExportOptions::default(), &mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        assert!(obj.starts_with("# Tube\n"));
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("v ")).count(),
+            16
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            12
+        );
+        // The first cross-section of the northward leg, with east to the right.
+        let vertices = obj.lines().skip(1).take(4).collect::<Vec<_>>();
+        assert_eq!(
+            vertices,
+            vec![
+                "v -1.00 0.00 3.00",
+                "v 2.00 0.00 3.00",
+                "v 2.00 0.00 -4.00",
+                "v -1.00 0.00 -4.00"
+            ]
+        );
+        assert!(obj.contains("v -0.10 10.00 0.10\n"));
+        assert!(obj.contains("f 9 10 14 13\n"));
+        assert!(obj.lines().last().unwrap() == "f 13 14 15 16");
+
+        let mut data = SurveyData::new();
+        data.title = String::from("Two\nlines");
+        let mut obj = Vec::new();
+        to_obj(&data, &ExportOptions::default(), &mut obj).unwrap();
+        assert_eq!(String::from_utf8(obj).unwrap(), "# Two lines\n");
+    }
+
+    #[test]
+    fn test_to_therion() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let mut th = Vec::new();
+        to_therion(&data, &ExportOptions::default(), &mut th).unwrap();
+        let th = String::from_utf8(th).unwrap();
+
+        assert!(th.starts_with(
+            "encoding utf-8\nsurvey NottsIIWithEntrance -title \"NottsIIWithEntrance\"\n  centreline\n"
+        ));
+        assert!(th.ends_with("  endcentreline\nendsurvey\n"));
+        let lines = th.lines().collect::<Vec<_>>();
+        let fixes = lines
+            .iter()
+            .filter(|line| line.starts_with("    fix "))
+            .count();
+        assert_eq!(fixes, 1904);
+        assert!(th.contains("    fix nottsii.entrance 66668.00 78303.00 319.00\n"));
+
+        let start = lines
+            .iter()
+            .position(|line| *line == "    data nosurvey from to");
+        let legs = lines[start.unwrap() + 1..]
+            .iter()
+            .take_while(|line| !line.starts_with("    station ") && !line.starts_with("  end"))
+            .count();
+        assert_eq!(legs, 1782);
+        let entrances = lines
+            .iter()
+            .filter(|line| line.starts_with("    station ") && line.ends_with(" \"\" entrance"))
+            .count();
+        assert_eq!(entrances, 3);
+        assert!(th.contains("    station nottsii.entrance \"\" entrance\n"));
+
+        assert_eq!(therion_name("cave.1"), "cave.1");
+        assert_eq!(therion_name("my cave@1"), "my_cave_1");
+        assert_eq!(therion_name(".1"), "_.1");
+        assert_eq!(therion_name(""), "_");
+
+        let mut data = SurveyData::new();
+        data.title = String::from("The \"Big\"\nCave");
+        let mut th = Vec::new();
+        to_therion(&data, &ExportOptions::default(), &mut th).unwrap();
+        let th = String::from_utf8(th).unwrap();
+        assert!(th.contains("\nsurvey The__Big__Cave -title \"The \"\"Big\"\" Cave\"\n"));
+    }
+
+    #[test]
+    fn test_to_graphml() {
+        let data = SurveyBuilder::new()
+            .title("Pots & pans")
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::ENTRANCE)
+            .add_station("a.2", Point::new(3.0, 4.0, 0.0), StationFlags::UNDERGROUND)
+            .add_station("a.3", Point::new(3.0, 4.0, -2.5), StationFlags::UNDERGROUND)
+            .add_station("b.1", Point::new(10.0, 0.0, 0.0), StationFlags::SURFACE)
+            .add_leg("a.1", "a.2")
+            .add_leg("a.2", "a.3")
+            .add_leg("a.1", "b.1")
+            .build()
+            .unwrap();
+        let graphml = to_graphml(&data, &ExportOptions::default());
+
+        assert_balanced_xml(&graphml);
+        assert!(graphml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml "));
+        assert!(graphml.contains("<desc>Pots &amp; pans</desc>"));
+        assert_eq!(graphml.matches("<node ").count(), 4);
+        assert_eq!(graphml.matches("<edge ").count(), 3);
+        assert!(graphml.contains(
+            "<node id=\"n0\"><data key=\"label\">a.1</data><data key=\"x\">0.00</data>\
+             <data key=\"y\">0.00</data><data key=\"z\">0.00</data>\
+             <data key=\"surface\">false</data><data key=\"underground\">false</data>\
+             <data key=\"entrance\">true</data>"
+        ));
+        assert!(graphml.contains(
+            "<edge id=\"e1\" source=\"n1\" target=\"n2\"><data key=\"length\">2.50</data></edge>"
+        ));
+
+        let graphml = to_graphml(&data, &ExportOptions::default().include_surface(false));
+        assert_eq!(graphml.matches("<node ").count(), 3);
+        assert_eq!(graphml.matches("<edge ").count(), 2);
+        assert!(!graphml.contains("id=\"n3\""));
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let graphml = to_graphml(&data, &ExportOptions::default());
+        assert_balanced_xml(&graphml);
+        assert_eq!(graphml.matches("<node ").count(), 1904);
+        assert_eq!(graphml.matches("<edge ").count(), 1782);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let dot = to_dot(&data, &ExportOptions::default());
+        assert!(dot.starts_with("graph {"));
+        assert!(dot.contains("[ label = \"nottsii.entrance\", shape = doublecircle]"));
+        assert_eq!(dot.matches(" -- ").count(), 1782);
+        let edge = dot.lines().find(|line| line.contains(" -- ")).unwrap();
+        let length = edge.split('"').nth(1).unwrap();
+        assert_eq!(length.split_once('.').unwrap().1.len(), 2);
+        assert!(!dot.contains("nottsii.committeepotentrance.entranceshaft_2.15\", shape"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Fuzzy string matching for station labels
+//!
+//! This module contains an implementation of the Jaro-Winkler similarity, which is used by
+//! [`SurveyData::search_labels`][`crate::data::SurveyData::search_labels`] to find stations whose
+//! labels are close to a possibly misspelled query.
+
+/// The maximum length of the common prefix which is rewarded by the Winkler adjustment.
+const MAX_PREFIX: usize = 4;
+
+/// The weight given to each character of the common prefix by the Winkler adjustment.
+const PREFIX_SCALE: f64 = 0.1;
+
+/// Calculate the Jaro similarity of two strings, from 0 for no similarity to 1 for identical
+/// strings. Characters are compared exactly, so callers should normalise case if required.
+pub(crate) fn jaro(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    // Characters only match if they are within this distance of each other in the two strings.
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    // Count the matched characters which appear in a different order in the two strings.
+    let a_order = a.iter().zip(&a_matched).filter(|(_, &m)| m).map(|(c, _)| c);
+    let b_order = b.iter().zip(&b_matched).filter(|(_, &m)| m).map(|(c, _)| c);
+    let transpositions = a_order.zip(b_order).filter(|(ca, cb)| ca != cb).count() / 2;
+
+    let matches = matches as f64;
+    (matches / a.len() as f64
+        + matches / b.len() as f64
+        + (matches - transpositions as f64) / matches)
+        / 3.0
+}
+
+/// Calculate the Jaro-Winkler similarity of two strings, from 0 for no similarity to 1 for
+/// identical strings. This is the [`jaro`] similarity with a bonus for strings which share a
+/// common prefix.
+pub(crate) fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let jaro = jaro(a, b);
+    let prefix = a
+        .chars()
+        .zip(b.chars())
+        .take(MAX_PREFIX)
+        .take_while(|(ca, cb)| ca == cb)
+        .count();
+    jaro + prefix as f64 * PREFIX_SCALE * (1.0 - jaro)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jaro_winkler() {
+        assert_eq!(jaro_winkler("entrance", "entrance"), 1.0);
+        assert_eq!(jaro_winkler("", ""), 1.0);
+        assert_eq!(jaro_winkler("abc", ""), 0.0);
+        assert_eq!(jaro_winkler("abc", "xyz"), 0.0);
+
+        // Reference values from the original descriptions of the measures.
+        assert!((jaro("martha", "marhta") - 0.944).abs() < 0.001);
+        assert!((jaro_winkler("martha", "marhta") - 0.961).abs() < 0.001);
+        assert!((jaro_winkler("dixon", "dicksonx") - 0.813).abs() < 0.001);
+        assert!(jaro_winkler("entrance", "entrence") > jaro_winkler("entrance", "inlet"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Data structures to represent survey legs
+
+use std::fmt::{Display, Formatter};
+
+/// Struct representation of a survey leg
+///
+/// Legs are stored as the edge weights of the graph held by a
+/// [`SurveyData`][`crate::data::SurveyData`] instance. The stations at either end of a leg can be
+/// found by looking up the edge's endpoints in the graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Leg {
+    /// The length of the survey leg in metres.
+    pub length: f64,
+    /// The loop closure error of the traverse containing this leg, where the Survex file provides
+    /// it. This is the ratio of the observed misclosure to the theoretical misclosure (the `E`
+    /// value reported by Survex), so values above 1.0 indicate a worse than expected closure.
+    /// Legs which are not part of a loop will have no error.
+    pub error: Option<f64>,
+    /// The style of survey which the leg was measured with.
+    pub style: LegStyle,
+    /// The flags which Survex recorded for the leg.
+    pub flags: LegFlags,
+    /// The first and last days on which the leg was surveyed, as Survex day numbers, where the
+    /// Survex file provides them. See [`date_range`][`Leg::date_range`] to convert these to
+    /// calendar dates.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub days: Option<(i32, i32)>,
+}
+
+impl Leg {
+    /// Create a new [`Leg`] with the given length, the default [`LegStyle`], no flags set and no
+    /// error information.
+    pub fn new(length: f64) -> Self {
+        Self {
+            length,
+            error: None,
+            style: LegStyle::default(),
+            flags: LegFlags::default(),
+            days: None,
+        }
+    }
+
+    /// Retrieve the first and last dates on which the leg was surveyed, which are the same if it
+    /// was surveyed on a single day. Returns [`None`] if the Survex file has no date for the leg,
+    /// including files which predate support for survey dates.
+    pub fn date_range(&self) -> Option<(SurveyDate, SurveyDate)> {
+        let (first, last) = self.days?;
+        Some((SurveyDate::from_days(first), SurveyDate::from_days(last)))
+    }
+}
+
+/// The Survex day number of the Unix epoch, 1st January 1970. The Survex library gives survey
+/// dates as seconds since the Unix epoch, which are converted to day numbers using this offset.
+pub(crate) const UNIX_EPOCH_DAYS: i64 = 25567;
+
+/// A calendar date on which a survey was carried out
+///
+/// Survex stores survey dates as day numbers, counting the days since 1st January 1900, which
+/// can be converted to a [`SurveyDate`] with [`SurveyDate::from_days`]. Dates are displayed in
+/// ISO 8601 format, such as `1995-05-01`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SurveyDate {
+    pub year: i32,
+    /// The month of the year, from 1 for January to 12 for December.
+    pub month: u32,
+    /// The day of the month, starting from 1.
+    pub day: u32,
+}
+
+impl SurveyDate {
+    /// Convert a Survex day number, which counts the days since 1st January 1900, into a
+    /// calendar date in the proleptic Gregorian calendar.
+    pub fn from_days(days: i32) -> Self {
+        // Count days from 1st March 0000, so that leap days fall at the end of each year, and
+        // split the count into 400 year eras which each contain the same number of days.
+        let days = i64::from(days) + 693_901;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self {
+            // The year of any i32 day number fits in an i32, and the month and day are small.
+            year: year as i32,
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+}
+
+impl Display for SurveyDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The flags which Survex can set on a survey leg
+///
+/// Each field corresponds to one of the `img_FLAG_*` constants in `img.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LegFlags {
+    /// The leg is a surface survey (`img_FLAG_SURFACE`).
+    pub surface: bool,
+    /// The leg is a duplicate of another leg, such as a resurvey (`img_FLAG_DUPLICATE`).
+    pub duplicate: bool,
+    /// The leg is a splay shot (`img_FLAG_SPLAY`).
+    pub splay: bool,
+}
+
+impl From<i32> for LegFlags {
+    /// Convert the `flags` value of an `img` struct, as set after reading a leg, into a
+    /// [`LegFlags`]. Unrecognised flags are ignored.
+    fn from(flags: i32) -> Self {
+        Self {
+            surface: flags & 0x01 != 0,
+            duplicate: flags & 0x02 != 0,
+            splay: flags & 0x04 != 0,
+        }
+    }
+}
+
+impl From<LegFlags> for i32 {
+    /// Convert a [`LegFlags`] into the `flags` value used by the Survex library when writing a
+    /// leg.
+    fn from(flags: LegFlags) -> Self {
+        let mut value = 0;
+        if flags.surface {
+            value |= 0x01;
+        }
+        if flags.duplicate {
+            value |= 0x02;
+        }
+        if flags.splay {
+            value |= 0x04;
+        }
+        value
+    }
+}
+
+/// The style of survey used to measure a leg
+///
+/// Survex records the style of the data each leg was produced from, which changes as different
+/// parts of a cave are read. Each variant corresponds to one of the `img_STYLE_*` constants in
+/// `img.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LegStyle {
+    /// The style is not known (`img_STYLE_UNKNOWN`).
+    Unknown,
+    /// Normal tape, compass and clino data, including topofil data (`img_STYLE_NORMAL`).
+    #[default]
+    Normal,
+    /// Diving data, where depth gauge readings are used instead of a clino
+    /// (`img_STYLE_DIVING`).
+    Diving,
+    /// Cartesian data, given as dx, dy and dz offsets (`img_STYLE_CARTESIAN`).
+    Cartesian,
+    /// Cylindrical polar data, where depth gauge readings are used alongside compass and tape
+    /// (`img_STYLE_CYLPOLAR`).
+    CylPolar,
+    /// A connection between stations with no survey data (`img_STYLE_NOSURVEY`).
+    NoSurvey,
+}
+
+impl From<i32> for LegStyle {
+    /// Convert the `style` value of an `img` struct into a [`LegStyle`]. Unrecognised values are
+    /// treated as [`LegStyle::Unknown`].
+    fn from(style: i32) -> Self {
+        match style {
+            0 => Self::Normal,
+            1 => Self::Diving,
+            2 => Self::Cartesian,
+            3 => Self::CylPolar,
+            4 => Self::NoSurvey,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<LegStyle> for i32 {
+    /// Convert a [`LegStyle`] into the `style` value used by the Survex library.
+    fn from(style: LegStyle) -> Self {
+        match style {
+            LegStyle::Unknown => -1,
+            LegStyle::Normal => 0,
+            LegStyle::Diving => 1,
+            LegStyle::Cartesian => 2,
+            LegStyle::CylPolar => 3,
+            LegStyle::NoSurvey => 4,
+        }
+    }
+}
+
+/// The loop closure error statistics of a traverse
+///
+/// When Survex closes a loop, it distributes the misclosure across the legs of each traverse in
+/// the loop and records how well the traverse fitted. These figures are stored in .3d files as
+/// `img_ERROR_INFO` items, and are available from
+/// [`SurveyData::loop_errors`][`crate::data::SurveyData::loop_errors`]. Each error is the ratio of
+/// the observed misclosure to the misclosure expected from the instrument errors, so values above
+/// 1.0 indicate a worse than expected closure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoopError {
+    /// The number of legs in the traverse.
+    pub n_legs: usize,
+    /// The length of the traverse in metres.
+    pub length: f64,
+    /// The ratio of the observed misclosure to the expected misclosure (`E`).
+    pub error: f64,
+    /// The ratio of the observed horizontal misclosure to the expected misclosure (`H`).
+    pub horizontal_error: f64,
+    /// The ratio of the observed vertical misclosure to the expected misclosure (`V`).
+    pub vertical_error: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_survey_date_from_days() {
+        let date = |year, month, day| SurveyDate { year, month, day };
+        assert_eq!(SurveyDate::from_days(0), date(1900, 1, 1));
+        assert_eq!(SurveyDate::from_days(58), date(1900, 2, 28));
+        assert_eq!(SurveyDate::from_days(59), date(1900, 3, 1));
+        assert_eq!(SurveyDate::from_days(25567), date(1970, 1, 1));
+        assert_eq!(SurveyDate::from_days(36583), date(2000, 2, 29));
+        assert_eq!(SurveyDate::from_days(-1), date(1899, 12, 31));
+        assert_eq!(SurveyDate::from_days(34818).to_string(), "1995-05-01");
+    }
+}
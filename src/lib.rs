@@ -17,7 +17,7 @@
 //! ```rust
 //! use std::path::PathBuf;
 //! use survex_rs::read::load_from_path;
-//! use survex_rs::station::Point;
+//! use survex_rs::point::Point;
 //!
 //! let path = PathBuf::from("tests/data/nottsii.3d");
 //! let data = load_from_path(path).unwrap();
@@ -39,6 +39,7 @@
 //! println!("{:#?}", station);
 //! // Station {
 //! //     label: "nottsii.entrance",
+//! //     original_label: None,
 //! //     coords: Point {
 //! //         x: 66668.0,
 //! //         y: 78303.0,
@@ -50,14 +51,12 @@
 //! //         right: None,
 //! //         up: None,
 //! //         down: None,
+//! //         unit: Metres,
 //! //     },
-//! //     surface: false,
-//! //     underground: false,
-//! //     entrance: true,
-//! //     exported: true,
-//! //     fixed: true,
-//! //     anonymous: false,
-//! //     wall: false,
+//! //     flags: StationFlags(
+//! //         ENTRANCE | EXPORTED | FIXED,
+//! //     ),
+//! //     raw_flags: 28,
 //! // }
 //! ```
 //!
@@ -74,7 +73,16 @@
 //! This project is currently in early development and is not ready for production use. The API is subject to change at
 //! any time and semantic versioning is not yet being used.
 
+pub mod builder;
 pub mod data;
+pub mod error;
+pub mod export;
+mod fuzzy;
+pub mod leg;
+pub mod point;
 pub mod read;
+mod spatial;
 pub mod station;
 pub mod survex;
+pub mod sync;
+pub mod write;
@@ -0,0 +1,489 @@
+//! Data structures to represent points in space
+
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Mul, Sub};
+
+/// A point in 3D space
+///
+/// Coordinates are given in metres, unless the [units][`crate::data::SurveyData::units`] of the
+/// survey are feet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Point {
+    /// Create a new [`Point`] with the given coordinates.
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Calculate the distance between two points. This is a simple Euclidean distance
+    /// calculation. The result is given in metres.
+    pub fn distance(&self, other: &Self) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
+            .sqrt()
+    }
+
+    /// Calculate the horizontal (plan) distance between two points in metres, ignoring the z
+    /// coordinate.
+    pub fn horizontal_distance(&self, other: &Self) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+
+    /// Calculate the vertical distance between two points in metres. This is always positive,
+    /// regardless of which point is higher.
+    pub fn vertical_distance(&self, other: &Self) -> f64 {
+        (other.z - self.z).abs()
+    }
+
+    /// Calculate the bearing from this point to another in degrees, clockwise from north (the
+    /// positive y axis), in the range 0 to 360. Returns 0 if the points are at the same position
+    /// in plan.
+    pub fn bearing(&self, other: &Self) -> f64 {
+        let bearing = (other.x - self.x).atan2(other.y - self.y).to_degrees();
+        if bearing < 0.0 {
+            bearing + 360.0
+        } else {
+            bearing
+        }
+    }
+
+    /// Calculate the gradient from this point to another in degrees from horizontal, in the range
+    /// -90 to 90. The gradient is positive if the other point is higher than this point. Returns 0
+    /// if the points are at the same position.
+    pub fn gradient(&self, other: &Self) -> f64 {
+        (other.z - self.z)
+            .atan2(self.horizontal_distance(other))
+            .to_degrees()
+    }
+
+    /// Calculate the point halfway between this point and another.
+    pub fn midpoint(&self, other: &Self) -> Self {
+        (*self + *other) * 0.5
+    }
+
+    /// Calculate the shortest distance from this point to the line segment between `a` and `b`.
+    /// This is the perpendicular distance to the line if the nearest point on the line lies
+    /// between `a` and `b`, or otherwise the distance to the nearer of `a` and `b`.
+    pub fn distance_to_segment(&self, a: &Self, b: &Self) -> f64 {
+        let segment = *b - *a;
+        let length_squared = segment.dot(&segment);
+        if length_squared == 0.0 {
+            return self.distance(a);
+        }
+        let t = ((*self - *a).dot(&segment) / length_squared).clamp(0.0, 1.0);
+        self.distance(&(*a + segment * t))
+    }
+
+    /// Calculate the dot product of this point and another, treating both as vectors.
+    fn dot(&self, other: &Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Calculate the indices of the tile containing this point in plan view, for a grid of
+    /// square tiles of `tile_size` metres with a tile corner at the origin. The z coordinate is
+    /// ignored.
+    pub fn tile(&self, tile_size: f64) -> (i64, i64) {
+        (
+            (self.x / tile_size).floor() as i64,
+            (self.y / tile_size).floor() as i64,
+        )
+    }
+
+    /// Project the point onto a 2D view, returning the horizontal and vertical coordinates of the
+    /// point in that view. See [`Projection`] for the axes of each view.
+    pub fn project(&self, projection: Projection) -> (f64, f64) {
+        match projection {
+            Projection::Plan => (self.x, self.y),
+            Projection::Elevation(bearing) => {
+                let bearing = bearing.to_radians();
+                (self.x * bearing.cos() - self.y * bearing.sin(), self.z)
+            }
+        }
+    }
+
+    /// Format the point as a comma-separated list of coordinates with the given number of decimal
+    /// places. See [`PointFormat`] to also change the separator.
+    pub fn format(&self, precision: usize) -> String {
+        self.display().precision(precision).to_string()
+    }
+
+    /// Create a [`PointFormat`] to display the point with a different precision or separator to
+    /// its [`Display`] implementation.
+    pub fn display(&self) -> PointFormat<'_> {
+        PointFormat {
+            point: self,
+            precision: 2,
+            separator: ", ",
+        }
+    }
+
+    /// Calculate the distance between two points in the given unit, rather than in metres. See
+    /// [`LengthUnit::convert`] to convert distances for surveys whose coordinates are not in
+    /// metres.
+    pub fn distance_in(&self, other: &Self, unit: LengthUnit) -> f64 {
+        LengthUnit::Metres.convert(self.distance(other), unit)
+    }
+}
+
+/// A unit of length used for survey coordinates
+///
+/// See [`SurveyData::units`][`crate::data::SurveyData::units`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LengthUnit {
+    #[default]
+    Metres,
+    /// International feet of exactly 0.3048 metres. The US survey foot differs from this by two
+    /// parts per million, which is far below the precision of a cave survey, so it is treated as
+    /// the same unit.
+    Feet,
+}
+
+impl LengthUnit {
+    /// The length of one of this unit in metres.
+    pub fn metres(self) -> f64 {
+        match self {
+            LengthUnit::Metres => 1.0,
+            LengthUnit::Feet => 0.3048,
+        }
+    }
+
+    /// Convert a length in this unit to the given unit.
+    pub fn convert(self, value: f64, to: LengthUnit) -> f64 {
+        value * self.metres() / to.metres()
+    }
+}
+
+/// A 2D view onto 3D survey data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// A plan view looking down, where the horizontal axis is x (east) and the vertical axis is
+    /// y (north).
+    Plan,
+    /// An elevation view looking horizontally towards the given bearing, in degrees clockwise
+    /// from north. The horizontal axis increases to the right of the viewer and the vertical axis
+    /// is z. For example, `Elevation(0.0)` looks north, with x (east) as the horizontal axis.
+    Elevation(f64),
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    /// Add the coordinates of two points, treating the second as an offset.
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    /// Subtract the coordinates of one point from another, giving the offset between them.
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Point {
+    type Output = Point;
+
+    /// Multiply every coordinate of the point by a scalar.
+    fn mul(self, scalar: f64) -> Point {
+        Point::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+impl From<[f64; 3]> for Point {
+    /// Create a [`Point`] from an array of `[x, y, z]` coordinates.
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<(f64, f64, f64)> for Point {
+    /// Create a [`Point`] from a tuple of `(x, y, z)` coordinates.
+    fn from((x, y, z): (f64, f64, f64)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Point> for [f64; 3] {
+    /// Convert a [`Point`] into an array of `[x, y, z]` coordinates.
+    fn from(point: Point) -> Self {
+        [point.x, point.y, point.z]
+    }
+}
+
+impl Display for Point {
+    /// Format the [`Point`] as a comma-separated list of coordinates, to two decimal places. Use
+    /// [`Point::display`] to change the precision or separator.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display().fmt(f)
+    }
+}
+
+/// A [`Point`] formatted with a configurable precision and separator
+///
+/// Created by [`Point::display`], which uses the same format as the [`Display`] implementation
+/// of [`Point`] until it is changed:
+///
+/// ```rust
+/// use survex_rs::point::Point;
+///
+/// let point = Point::new(66668.0, 78303.0, 319.125);
+/// assert_eq!(point.display().to_string(), "66668.00, 78303.00, 319.12");
+/// assert_eq!(point.display().precision(1).separator(" ").to_string(), "66668.0 78303.0 319.1");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PointFormat<'a> {
+    point: &'a Point,
+    precision: usize,
+    separator: &'a str,
+}
+
+impl<'a> PointFormat<'a> {
+    /// Set the number of decimal places given for each coordinate.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set the string placed between each coordinate.
+    pub fn separator(mut self, separator: &'a str) -> Self {
+        self.separator = separator;
+        self
+    }
+}
+
+impl Display for PointFormat<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (p, sep) = (self.precision, self.separator);
+        let Point { x, y, z } = self.point;
+        write!(f, "{x:.p$}{sep}{y:.p$}{sep}{z:.p$}")
+    }
+}
+
+/// An axis-aligned bounding box in 3D space
+///
+/// Coordinates are given in metres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// The corner of the box with the smallest coordinates on every axis.
+    pub min: Point,
+    /// The corner of the box with the largest coordinates on every axis.
+    pub max: Point,
+}
+
+impl BoundingBox {
+    /// Create a new [`BoundingBox`] from the given minimum and maximum corners.
+    pub fn new(min: Point, max: Point) -> Self {
+        Self { min, max }
+    }
+
+    /// Calculate the smallest [`BoundingBox`] containing all of the given points, or [`None`] if
+    /// there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bbox = Self::new(first, first);
+        for p in points {
+            bbox.min = Point::new(
+                bbox.min.x.min(p.x),
+                bbox.min.y.min(p.y),
+                bbox.min.z.min(p.z),
+            );
+            bbox.max = Point::new(
+                bbox.max.x.max(p.x),
+                bbox.max.y.max(p.y),
+                bbox.max.z.max(p.z),
+            );
+        }
+        Some(bbox)
+    }
+
+    /// Calculate the centre of the box.
+    pub fn center(&self) -> Point {
+        Point::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    /// Calculate the size of the box along each axis in metres, returned as a [`Point`] of
+    /// (width, length, height).
+    pub fn size(&self) -> Point {
+        Point::new(
+            self.max.x - self.min.x,
+            self.max.y - self.min.y,
+            self.max.z - self.min.z,
+        )
+    }
+
+    /// Calculate the volume of the box in cubic metres.
+    pub fn volume(&self) -> f64 {
+        let size = self.size();
+        size.x * size.y * size.z
+    }
+
+    /// Calculate the [`BoundingBox`] of the space shared by this box and another, or [`None`] if
+    /// they do not intersect. Boxes which only touch at a face, edge or corner are considered to
+    /// intersect, producing a box with zero volume.
+    pub fn intersection(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        let min = Point::new(
+            self.min.x.max(other.min.x),
+            self.min.y.max(other.min.y),
+            self.min.z.max(other.min.z),
+        );
+        let max = Point::new(
+            self.max.x.min(other.max.x),
+            self.max.y.min(other.max.y),
+            self.max.z.min(other.max.z),
+        );
+        if min.x > max.x || min.y > max.y || min.z > max.z {
+            return None;
+        }
+        Some(Self::new(min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_in() {
+        let a = Point::new(0.0, 0.0, 0.0);
+        let b = Point::new(3.048, 0.0, 0.0);
+        assert_eq!(a.distance_in(&b, LengthUnit::Metres), 3.048);
+        assert!((a.distance_in(&b, LengthUnit::Feet) - 10.0).abs() < 1e-9);
+        assert!((LengthUnit::Feet.convert(10.0, LengthUnit::Metres) - 3.048).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_format() {
+        let point = Point::new(66668.0, -78303.25, 319.123456);
+        assert_eq!(point.to_string(), "66668.00, -78303.25, 319.12");
+        assert_eq!(point.format(2), point.to_string());
+        assert_eq!(point.format(0), "66668, -78303, 319");
+        assert_eq!(point.format(4), "66668.0000, -78303.2500, 319.1235");
+        assert_eq!(
+            point.display().precision(3).separator("\t").to_string(),
+            "66668.000\t-78303.250\t319.123"
+        );
+    }
+
+    #[test]
+    fn test_tile() {
+        assert_eq!(Point::new(0.0, 0.0, 0.0).tile(10.0), (0, 0));
+        assert_eq!(Point::new(9.99, 10.0, 5.0).tile(10.0), (0, 1));
+        assert_eq!(Point::new(-0.1, -10.0, 5.0).tile(10.0), (-1, -1));
+        assert_eq!(Point::new(66668.0, 78303.0, 319.0).tile(100.0), (666, 783));
+    }
+
+    #[test]
+    fn test_bounding_box_intersection() {
+        let a = BoundingBox::new(Point::new(0.0, 0.0, 0.0), Point::new(2.0, 2.0, 2.0));
+        let b = BoundingBox::new(Point::new(1.0, 1.0, 1.0), Point::new(3.0, 4.0, 5.0));
+        let c = BoundingBox::new(Point::new(2.0, 0.0, 0.0), Point::new(3.0, 1.0, 1.0));
+        let d = BoundingBox::new(Point::new(5.0, 5.0, 5.0), Point::new(6.0, 6.0, 6.0));
+
+        let ab = a.intersection(&b).unwrap();
+        assert_eq!(ab.min, Point::new(1.0, 1.0, 1.0));
+        assert_eq!(ab.max, Point::new(2.0, 2.0, 2.0));
+        assert_eq!(ab.volume(), 1.0);
+        assert_eq!(a.intersection(&c).unwrap().volume(), 0.0);
+        assert_eq!(a.intersection(&d), None);
+    }
+
+    #[test]
+    fn test_bounding_box_from_points() {
+        assert_eq!(BoundingBox::from_points([]), None);
+
+        let bbox = BoundingBox::from_points([
+            Point::new(1.0, -2.0, 3.0),
+            Point::new(-1.0, 2.0, 0.0),
+            Point::new(0.0, 0.0, 5.0),
+        ])
+        .unwrap();
+        assert_eq!(bbox.min, Point::new(-1.0, -2.0, 0.0));
+        assert_eq!(bbox.max, Point::new(1.0, 2.0, 5.0));
+        assert_eq!(bbox.volume(), 40.0);
+        assert_eq!(bbox.center(), Point::new(0.0, 0.0, 2.5));
+        assert_eq!(bbox.size(), Point::new(2.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn test_project() {
+        let p = Point::new(1.0, 2.0, 3.0);
+        assert_eq!(p.project(Projection::Plan), (1.0, 2.0));
+        assert_eq!(p.project(Projection::Elevation(0.0)), (1.0, 3.0));
+
+        let (u, v) = p.project(Projection::Elevation(90.0));
+        assert!((u + 2.0).abs() < 1e-9);
+        assert_eq!(v, 3.0);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Point::new(1.0, 2.0, 3.0);
+        let b = Point::new(0.5, -1.0, 4.0);
+        assert_eq!(a + b, Point::new(1.5, 1.0, 7.0));
+        assert_eq!(a - b, Point::new(0.5, 3.0, -1.0));
+        assert_eq!(a * 2.0, Point::new(2.0, 4.0, 6.0));
+        assert_eq!(a.midpoint(&b), Point::new(0.75, 0.5, 3.5));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let p = Point::new(1.0, 2.0, 3.0);
+        assert_eq!(Point::from([1.0, 2.0, 3.0]), p);
+        assert_eq!(Point::from((1.0, 2.0, 3.0)), p);
+        let array: [f64; 3] = p.into();
+        assert_eq!(array, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_distance_to_segment() {
+        let a = Point::new(0.0, 0.0, 0.0);
+        let b = Point::new(10.0, 0.0, 0.0);
+        assert_eq!(Point::new(5.0, 3.0, 4.0).distance_to_segment(&a, &b), 5.0);
+        assert_eq!(Point::new(2.0, 0.0, 0.0).distance_to_segment(&a, &b), 0.0);
+        assert_eq!(Point::new(-3.0, 4.0, 0.0).distance_to_segment(&a, &b), 5.0);
+        assert_eq!(Point::new(13.0, 0.0, 4.0).distance_to_segment(&a, &b), 5.0);
+        assert_eq!(Point::new(3.0, 4.0, 0.0).distance_to_segment(&a, &a), 5.0);
+    }
+
+    #[test]
+    fn test_bearing_and_gradient() {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let cases = [
+            (Point::new(0.0, 1.0, 0.0), 0.0),
+            (Point::new(1.0, 1.0, 0.0), 45.0),
+            (Point::new(1.0, 0.0, 0.0), 90.0),
+            (Point::new(1.0, -1.0, 0.0), 135.0),
+            (Point::new(0.0, -1.0, 0.0), 180.0),
+            (Point::new(-1.0, -1.0, 0.0), 225.0),
+            (Point::new(-1.0, 0.0, 0.0), 270.0),
+            (Point::new(-1.0, 1.0, 0.0), 315.0),
+        ];
+        for (p, bearing) in cases {
+            assert!((origin.bearing(&p) - bearing).abs() < 1e-9, "{}", p);
+        }
+        assert_eq!(origin.bearing(&Point::new(0.0, 0.0, 5.0)), 0.0);
+
+        let p = Point::new(3.0, 4.0, 5.0);
+        assert_eq!(origin.horizontal_distance(&p), 5.0);
+        assert_eq!(origin.vertical_distance(&p), 5.0);
+        assert_eq!(p.vertical_distance(&origin), 5.0);
+        assert!((origin.gradient(&p) - 45.0).abs() < 1e-9);
+        assert!((p.gradient(&origin) + 45.0).abs() < 1e-9);
+        assert_eq!(origin.gradient(&Point::new(0.0, 0.0, -2.0)), -90.0);
+        assert_eq!(origin.gradient(&origin), 0.0);
+    }
+}
@@ -1,186 +1,721 @@
 //! Helper functions for reading Survex files
 //!
-//! At present, this module only contains a single function:
-//! [`load_from_path`][`crate::read::load_from_path`]. Refer to the documentation for that function,
+//! The main entry point is [`load_from_path`][`crate::read::load_from_path`]. If you need to
+//! control how the file is read, use
+//! [`load_from_path_with_options`][`crate::read::load_from_path_with_options`] with a
+//! [`LoadOptions`][`crate::read::LoadOptions`] instance. Projects made up of several Survex files
+//! can be read in one go with [`load_from_manifest`][`crate::read::load_from_manifest`], and files
+//! held in memory can be read with [`load_from_bytes`][`crate::read::load_from_bytes`]. To process
+//! the items in a file one at a time without building a [`SurveyData`] instance, use a
+//! [`SurveyReader`][`crate::read::SurveyReader`]. Refer to the documentation for those functions,
 //! or the [examples in the documentation index][`crate`] for more information.
 
-use crate::data::SurveyData;
-use crate::station::Point;
+use crate::data::{LabelCollision, SurveyData};
+use crate::error::ReadError;
+use crate::leg::{Leg, LegFlags, LegStyle, LoopError, UNIX_EPOCH_DAYS};
+use crate::point::{BoundingBox, LengthUnit, Point};
+use crate::station::{StationFlags, LRUD};
 use crate::survex;
 use log::trace;
-use std::error::Error;
-use std::ffi::{c_char, CStr};
-use std::path::PathBuf;
+use petgraph::graph::NodeIndex;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+/// Options which control how a Survex file is read by
+/// [`load_from_path_with_options`][`crate::read::load_from_path_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadOptions {
+    /// By default, stations which share a label are merged into a single station, with the
+    /// coordinates of the last one read. If this option is set, stations which share a label but
+    /// have different coordinates are instead kept as distinct stations and the conflict is
+    /// recorded in [`SurveyData::label_conflicts`].
+    pub detect_duplicate_labels: bool,
+    /// This crate assumes that z coordinates are elevations, so that a higher z value is higher
+    /// up. If the data being read uses a z axis which is positive downwards (i.e. z is a depth),
+    /// set this option to negate every z coordinate as it is read, so that the assumption holds.
+    pub z_down: bool,
+}
+
 /// Create a [`SurveyData`] instance from a Survex file.
 ///
 /// The path to the Survex file will be passed to the binding to the Survex C library, which will
 /// open and read the file. The data within the file will be iterated over to build a list of
 /// [Stations][`crate::station::Station`] and a graph of connections between them. The resulting
 /// [`SurveyData`] instance will be returned.
-pub fn load_from_path(path: PathBuf) -> Result<SurveyData, Box<dyn Error>> {
-    // Convert the path to the format required by img.c
-    let filename = path
-        .to_str()
-        .expect("Could not convert path to string")
-        .as_ptr() as *const c_char;
+///
+/// As well as Survex .3d files, the Survex library can read Survex .pos files, which contain
+/// station positions but no legs, along with some formats produced by other software. The format
+/// is detected from the file extension or, failing that, the contents of the file. Files with no
+/// legs will produce a graph with no edges.
+///
+/// If the file cannot be opened or contains invalid data, a [`ReadError`] describing the problem
+/// is returned.
+pub fn load_from_path(path: PathBuf) -> Result<SurveyData, ReadError> {
+    load_from_path_with_progress(path, |_| {})
+}
 
-    // Create an SurveyData instance to store and update data as it is read.
-    let mut data = SurveyData::new();
+/// Create a [`SurveyData`] instance from a Survex file, using the given [`LoadOptions`].
+///
+/// See [`load_from_path`][`crate::read::load_from_path`] for more information.
+pub fn load_from_path_with_options(
+    path: PathBuf,
+    options: LoadOptions,
+) -> Result<SurveyData, ReadError> {
+    load(path, options, &mut |_| {})
+}
 
-    // The way Survex 3D file reading works is that it will first spit out a bunch of coordinates
-    // and centrelines (determined by MOVE and LINE) commands, and it will then later give names
-    // to those coordinates by means of a LABEL command. As such, we will store the connections
-    // between two coordinates in a vector and then later - once we have read the full .3d file and
-    // have labels for all sets of coordinates - add the connections to the graph.
-    let mut connections = Vec::new();
+/// Create a [`SurveyData`] instance from a Survex file, reporting progress as the file is read,
+/// such as for updating a progress bar while loading a large file.
+///
+/// `callback` is called with the number of items (such as stations, legs and cross-sections)
+/// which have been read so far after every [`PROGRESS_INTERVAL`] items, and once more with the
+/// final count when reading is complete. The Survex library does not report the total number of
+/// items in a file before it is read, so progress can only be reported as a running count.
+///
+/// See [`load_from_path`][`crate::read::load_from_path`] for more information.
+pub fn load_from_path_with_progress(
+    path: PathBuf,
+    mut callback: impl FnMut(usize),
+) -> Result<SurveyData, ReadError> {
+    load(path, LoadOptions::default(), &mut callback)
+}
 
-    // These variables are used to store the data which is returned by each call to img_read_item.
-    // After a call to img_read_item, pimg will be updated with information from the current item,
-    // and p will be updated with the latest set of coordinates.
-
-    // (x, y, z) and label are used to store the previous label and set of coordinates after a
-    // call to img_read_item, as the next call may require them (such as in the case of a LINE
-    // command to create a leg between two points).
-    let pimg;
-    let (mut x, mut y, mut z) = (-1.0, -1.0, -1.0);
-    let mut label = "";
-    let mut p = survex::img_point {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
-    };
+/// Create a [`SurveyData`] instance from a single survey within a Survex file, such as one branch
+/// of a large cave.
+///
+/// `survey` is the full name of the survey, such as `nottsii.mainstreamway`. The Survex library
+/// skips all stations and legs outside the survey as it reads the file, which is much faster than
+/// loading the whole file and then using [`SurveyData::subsurvey`] for large files. The survey
+/// name is removed from the start of every station label, so `nottsii.mainstreamway.1` is
+/// labelled `1`, and the [title][`SurveyData::title`] is set to the final component of the survey
+/// name.
+///
+/// See [`load_from_path`][`crate::read::load_from_path`] for more information.
+pub fn load_survey_from_path(path: PathBuf, survey: &str) -> Result<SurveyData, ReadError> {
+    let reader = SurveyReader::open_survey(path, survey)?;
+    read_survey(reader, LoadOptions::default(), &mut |_| {})
+}
+
+/// The namespace used to generate the UUID v5 labels given to anonymous stations.
+const ANONYMOUS_NAMESPACE: Uuid = Uuid::from_u128(0x6c1a_3f0e_8d4b_4e27_9b65_2f7d_0c8e_a913);
+
+/// The number of items read between each call to the progress callback passed to
+/// [`load_from_path_with_progress`].
+pub const PROGRESS_INTERVAL: usize = 1000;
+
+/// Open a Survex file, read it with the given options and close it again.
+fn load(
+    path: PathBuf,
+    options: LoadOptions,
+    progress: &mut dyn FnMut(usize),
+) -> Result<SurveyData, ReadError> {
+    read_survey(SurveyReader::open(path)?, options, progress)
+}
 
-    // Open the Survex file and check that it was successful.
+/// Create a [`SurveyData`] instance from the contents of a Survex file held in memory.
+///
+/// The Survex C library can only read from a file, so the data is written to a temporary file
+/// which is then read in the same way as [`load_from_path`][`crate::read::load_from_path`]. The
+/// temporary file is removed once reading is complete, whether or not it was successful.
+pub fn load_from_bytes(data: &[u8]) -> Result<SurveyData, ReadError> {
+    let mut file = tempfile::Builder::new().suffix(".3d").tempfile()?;
+    file.write_all(data)?;
+    file.flush()?;
     trace!(
-        "Opening Survex file '{:?}' in load_from_path function via Survex img library.",
-        path
+        "Wrote {} bytes to temporary file '{:?}'.",
+        data.len(),
+        file.path()
     );
-    unsafe {
-        pimg = survex::img_open_survey(filename, ptr::null_mut());
+    load_from_path_with_options(file.path().to_path_buf(), LoadOptions::default())
+}
+
+/// Create a [`SurveyData`] instance from a Survex file which has already been opened, such as a
+/// file descriptor passed to a sandboxed process which can not open the file by path.
+///
+/// The file is read from its current position to the end, and is closed once reading is
+/// complete. The Survex C library can only read from a path, so the contents are usually read
+/// into memory and passed to [`load_from_bytes`][`crate::read::load_from_bytes`], which writes
+/// them to a temporary file. On Linux, a regular file which has not yet been read from is
+/// instead opened through `/proc/self/fd`, which avoids copying it. The copy is used if this
+/// fails, such as when `/proc` is not mounted.
+pub fn load_from_file(mut file: fs::File) -> Result<SurveyData, ReadError> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::io::Seek;
+        use std::os::fd::AsRawFd;
+
+        if file.metadata()?.is_file() && file.stream_position()? == 0 {
+            let path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+            match load_from_path(path) {
+                Err(ReadError::NotFound(_) | ReadError::Unreadable(..)) => {}
+                result => return result,
+            }
+            trace!("Could not open file descriptor via /proc. Copying the file instead.");
+        }
+    }
+
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut file, &mut data)?;
+    load_from_bytes(&data)
+}
+
+/// Summary information about a Survex file, read without loading its stations and legs
+///
+/// See [`read_metadata`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurveyMetadata {
+    /// The title of the survey.
+    pub title: String,
+    /// The coordinate system of the survey, if the file specifies one.
+    pub coordinate_system: Option<String>,
+    /// The datestamp of the file, if there is one. See [`SurveyReader::datestamp`].
+    pub datestamp: Option<String>,
+    /// The time at which the file was created, if known.
+    pub timestamp: Option<SystemTime>,
+    /// The version of the Survex file format.
+    pub format_version: i32,
+    /// The character which separates the components of station labels.
+    pub separator: char,
+    /// Whether the file contains an extended elevation rather than real coordinates.
+    pub extended_elevation: bool,
+    /// The unit of the coordinates in the file.
+    pub units: LengthUnit,
+    /// The number of stations which a full load of the file would give.
+    pub stations: usize,
+    /// The number of legs which a full load of the file would give.
+    pub legs: usize,
+    /// The bounding box of every station position in the file, or [`None`] if there are no
+    /// stations.
+    pub bounding_box: Option<BoundingBox>,
+}
+
+/// Read the header of a Survex file and summarise its contents, such as for listing surveys in a
+/// file browser.
+///
+/// Every item in the file is read to count the stations and legs and find the bounding box of the
+/// stations, but no [`SurveyData`] is built, which makes this much faster than
+/// [`load_from_path`][`crate::read::load_from_path`]. Station counts are calculated in the same
+/// way as a full load: stations listed more than once are counted once, and each anonymous
+/// station is counted separately.
+pub fn read_metadata(path: PathBuf) -> Result<SurveyMetadata, ReadError> {
+    let reader = SurveyReader::open(path)?;
+    let mut metadata = SurveyMetadata {
+        title: reader.title.clone(),
+        coordinate_system: reader.coordinate_system.clone(),
+        datestamp: reader.datestamp.clone(),
+        timestamp: reader.timestamp,
+        format_version: reader.format_version,
+        separator: reader.separator,
+        extended_elevation: reader.extended_elevation,
+        units: reader.units,
+        stations: 0,
+        legs: 0,
+        bounding_box: None,
+    };
+
+    let mut labels = HashSet::new();
+    let mut anonymous = 0;
+    let mut positions = Vec::new();
+    for item in reader {
+        match item? {
+            Item::Line { .. } => metadata.legs += 1,
+            Item::Label {
+                label,
+                coords,
+                flags,
+                ..
+            } => {
+                if flags.contains(StationFlags::ANONYMOUS) {
+                    anonymous += 1;
+                } else {
+                    labels.insert(label);
+                }
+                positions.push(coords);
+            }
+            _ => {}
+        }
     }
-    if pimg.is_null() {
-        trace!("Survex library returned a null pointer. Read failed.");
-        return Err("Could not open Survex file".into());
+    metadata.stations = labels.len() + anonymous;
+    metadata.bounding_box = BoundingBox::from_points(positions);
+    Ok(metadata)
+}
+
+/// An item read from a Survex file by a [`SurveyReader`]
+///
+/// Each variant corresponds to one of the item types returned by `img_read_item` in `img.h`.
+/// Coordinates are given exactly as stored in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    /// Move to a new position without drawing a leg (`img_MOVE`).
+    Move(Point),
+    /// A leg from the previous position to a new position (`img_LINE`). The new position becomes
+    /// the start of the next leg.
+    Line {
+        from: Point,
+        to: Point,
+        style: LegStyle,
+        flags: LegFlags,
+        /// The first and last days on which the leg was surveyed. See [`Leg::days`].
+        days: Option<(i32, i32)>,
+    },
+    /// A cross marking a station position, only found in very old files (`img_CROSS`).
+    Cross(Point),
+    /// A station with its label and flags (`img_LABEL`). A station may be listed more than once.
+    Label {
+        label: String,
+        coords: Point,
+        flags: StationFlags,
+        raw_flags: u8,
+    },
+    /// The LRUD measurements at a station (`img_XSECT`).
+    Xsect { label: String, lrud: LRUD },
+    /// The end of a passage made up of the preceding cross-sections (`img_XSECT_END`).
+    XsectEnd,
+    /// The loop closure error statistics of the traverse made up of the preceding legs
+    /// (`img_ERROR_INFO`).
+    ErrorInfo(LoopError),
+}
+
+/// Reads the items in a Survex file one at a time
+///
+/// [`load_from_path`][`crate::read::load_from_path`] reads a whole file into a [`SurveyData`]
+/// instance, which may use a lot of memory for very large files. A [`SurveyReader`] instead
+/// yields each [`Item`] in the file as it is read, so that the caller can decide what to keep:
+///
+/// ```rust
+/// use std::path::PathBuf;
+/// use survex_rs::read::{Item, SurveyReader};
+///
+/// let reader = SurveyReader::open(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+/// let mut length = 0.0;
+/// for item in reader {
+///     if let Item::Line { from, to, .. } = item.unwrap() {
+///         length += from.distance(&to);
+///     }
+/// }
+/// ```
+///
+/// The header information of the file is available as soon as it is opened. Errors are returned
+/// as [`Err`] items, after which the iterator ends. The file is closed when the reader is
+/// dropped.
+pub struct SurveyReader {
+    pimg: *mut survex::img,
+    title: String,
+    coordinate_system: Option<String>,
+    datestamp: Option<String>,
+    timestamp: Option<SystemTime>,
+    format_version: i32,
+    separator: char,
+    extended_elevation: bool,
+    units: LengthUnit,
+    /// The position reached by the most recent MOVE or LINE item, which is the start of the next
+    /// leg.
+    position: Point,
+    /// The label of the most recent LABEL or XSECT item, which the Survex library may reuse for
+    /// the next cross-section.
+    label: String,
+    finished: bool,
+}
+
+impl SurveyReader {
+    /// Open a Survex file for reading. See [`load_from_path`][`crate::read::load_from_path`] for
+    /// the formats which can be read.
+    pub fn open(path: PathBuf) -> Result<Self, ReadError> {
+        Self::open_with_survey(path, None)
     }
 
-    // Read the data from the Survex file - loop through calls to img_read_item until it returns
-    // a value below zero which indicates that the end of the data has been reached (-1) or that
-    // there is an error (-2).
-    trace!("Reading Survex file in load_from_path function.");
-    loop {
-        let result = unsafe { survex::img_read_item(pimg, &mut p) };
-
-        #[allow(clippy::if_same_then_else)]
-        if result == -2 {
-            // Bad data in Survex file
-            panic!("Bad data in Survex file.");
-        } else if result == -1 {
-            trace!("STOP: End of Survex file reached.");
-            // STOP command
-            break;
-        } else if result == 0 {
-            // MOVE command
-            (x, y, z) = (p.x, p.y, p.z);
-            trace!("MOVE: {}, {}, {}.", x, y, z);
-        } else if result == 1 {
-            // LINE command
-            // At this point (x, y, z) will have been set by a previous MOVE command. We can use
-            // the previous coordinates to create a connection between the previous station and
-            // the current station. After the 3d file has been read, we can use the connections
-            // vector to add the connections to the graph.
-            let from_coords = Point::new(x, y, z);
-            let to_coords = Point::new(p.x, p.y, p.z);
-            connections.push((from_coords, to_coords));
-            trace!("LINE: {} -> {}.", from_coords, to_coords);
-            (x, y, z) = (p.x, p.y, p.z);
-        } else if result == 2 {
-            // CROSS command
-            trace!("CROSS command received. Ignoring.");
-        } else if result == 3 {
-            // LABEL command
-            let flags;
-            unsafe {
-                label = CStr::from_ptr((*pimg).label).to_str().unwrap();
-                flags = (*pimg).flags & 0x7f;
+    /// Open a single survey within a Survex file for reading. See
+    /// [`load_survey_from_path`][`crate::read::load_survey_from_path`] for details.
+    pub fn open_survey(path: PathBuf, survey: &str) -> Result<Self, ReadError> {
+        let survey =
+            CString::new(survey).map_err(|_| ReadError::InvalidSurvey(String::from(survey)))?;
+        Self::open_with_survey(path, Some(&survey))
+    }
+
+    /// Open a Survex file, restricted to the given survey if there is one, and read its header.
+    fn open_with_survey(path: PathBuf, survey: Option<&CStr>) -> Result<Self, ReadError> {
+        // Convert the path to the format required by img.c. This must be a NUL-terminated C string,
+        // as the Survex library reads the filename until it finds a NUL byte, so a path which
+        // contains one cannot be opened.
+        let filename = path
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| ReadError::InvalidPath(path.clone()))?;
+
+        // Check that the file can be opened before passing it to the Survex library, which only
+        // reports a generic error, so that a missing file can be told apart from one which
+        // cannot be read. The Survex library is also built without a check for directories, and
+        // on some platforms a directory can be opened as a file. Reading the header then fails,
+        // leaving the buffer used to detect the file format uninitialised, so directories must
+        // be rejected here.
+        if let Err(err) = check_readable(&path) {
+            trace!(
+                "Path '{:?}' could not be opened: {}. Read failed.",
+                path,
+                err
+            );
+            return Err(match err.kind() {
+                io::ErrorKind::NotFound => ReadError::NotFound(path),
+                _ => ReadError::Unreadable(path, err),
+            });
+        }
+
+        // Open the Survex file and check that it was successful.
+        trace!("Opening Survex file '{:?}' via Survex img library.", path);
+        let survey = survey.map_or(ptr::null(), |survey| survey.as_ptr());
+        let pimg = unsafe { survex::img_open_survey(filename.as_ptr(), survey) };
+        if pimg.is_null() {
+            let code = unsafe { survex::img_error() };
+            trace!("Survex library returned a null pointer with error {code}. Read failed.");
+            return Err(match code {
+                survex::img_errcode_IMG_FILENOTFOUND => ReadError::NotFound(path),
+                survex::img_errcode_IMG_BADFORMAT => ReadError::BadFormat,
+                survex::img_errcode_IMG_TOONEW => ReadError::TooNew,
+                _ => ReadError::OpenFailed,
+            });
+        }
+
+        // Read the file header information, which is available as soon as the file is opened.
+        let coordinate_system = unsafe { optional_string_from_ptr((*pimg).cs) };
+        let units = units_from_coordinate_system(coordinate_system.as_deref());
+        let reader = unsafe {
+            // time_t is not an i64 on every platform, and c_char is signed on some platforms and
+            // unsigned on others, so these casts are only unnecessary on some.
+            #[allow(clippy::unnecessary_cast)]
+            Self {
+                pimg,
+                title: optional_string_from_ptr((*pimg).title).unwrap_or_default(),
+                coordinate_system,
+                // The library uses "?" to indicate that no date information is available.
+                datestamp: optional_string_from_ptr((*pimg).datestamp)
+                    .filter(|datestamp| datestamp != "?"),
+                timestamp: timestamp_from_numeric((*pimg).datestamp_numeric as i64),
+                format_version: (*pimg).version,
+                separator: (*pimg).separator as u8 as char,
+                extended_elevation: (*pimg).is_extended_elevation != 0,
+                units,
+                position: Point::new(-1.0, -1.0, -1.0),
+                label: String::new(),
+                finished: false,
+            }
+        };
+        trace!("Survey title is '{}'.", reader.title);
+        trace!("Coordinate system is {:?}.", reader.coordinate_system);
+        trace!(
+            "Datestamp is {:?}, format version is {}.",
+            reader.datestamp,
+            reader.format_version
+        );
+        Ok(reader)
+    }
+
+    /// Retrieve the title of the survey, which is empty if the file does not specify one.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Retrieve the coordinate system of the survey, if the file specifies one. See
+    /// [`SurveyData::coordinate_system`].
+    pub fn coordinate_system(&self) -> Option<&str> {
+        self.coordinate_system.as_deref()
+    }
+
+    /// Retrieve the datestamp of the file, if it has one. See [`SurveyData::datestamp`].
+    pub fn datestamp(&self) -> Option<&str> {
+        self.datestamp.as_deref()
+    }
+
+    /// Retrieve the time at which the file was produced, if known.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// Retrieve the version of the Survex file format. See [`SurveyData::format_version`].
+    pub fn format_version(&self) -> i32 {
+        self.format_version
+    }
+
+    /// Retrieve the character which separates the components of station labels.
+    pub fn separator(&self) -> char {
+        self.separator
+    }
+
+    /// Retrieve whether the file is an extended elevation. See
+    /// [`SurveyData::extended_elevation`].
+    pub fn is_extended_elevation(&self) -> bool {
+        self.extended_elevation
+    }
+
+    /// Retrieve the unit of the coordinates in the file. See [`SurveyData::units`].
+    pub fn units(&self) -> LengthUnit {
+        self.units
+    }
+
+    /// Read the next item from the file, returning [`None`] at the end of the data.
+    fn read_item(&mut self) -> Option<Result<Item, ReadError>> {
+        let mut p = survex::img_point {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let result = unsafe { survex::img_read_item(self.pimg, &mut p) };
+        let point = Point::new(p.x, p.y, p.z);
+        let pimg = self.pimg;
+
+        let item = match result {
+            -2 => {
+                trace!("BAD: Survex library reported bad data.");
+                return Some(Err(ReadError::BadData));
             }
-            let coords = Point::new(p.x, p.y, p.z);
-            let (station, _) = data.add_or_update(coords, label);
-            trace!("LABEL: {} -> {}.", coords, label);
-
-            // Set the flags for the station
-            if flags & 0x01 != 0 {
-                station.borrow_mut().surface = true;
-                trace!("LABEL: surface flag set for station '{}'.", label);
+            -1 => {
+                trace!("STOP: End of Survex file reached.");
+                return None;
             }
-            if flags & 0x02 != 0 {
-                station.borrow_mut().underground = true;
-                trace!("LABEL: underground flag set for station '{}'.", label);
+            0 => {
+                trace!("MOVE: {}.", point);
+                self.position = point;
+                Item::Move(point)
             }
-            if flags & 0x04 != 0 {
-                station.borrow_mut().entrance = true;
-                trace!("LABEL: entrance flag set for station '{}'.", label);
+            1 => {
+                let from = std::mem::replace(&mut self.position, point);
+                trace!("LINE: {} -> {}.", from, point);
+                // time_t is not an i64 on every platform, so the casts are only unnecessary on
+                // some.
+                #[allow(clippy::unnecessary_cast)]
+                unsafe {
+                    Item::Line {
+                        from,
+                        to: point,
+                        style: LegStyle::from((*pimg).style),
+                        flags: LegFlags::from((*pimg).flags),
+                        days: days_from_dates((*pimg).date1 as i64, (*pimg).date2 as i64),
+                    }
+                }
             }
-            if flags & 0x08 != 0 {
-                station.borrow_mut().exported = true;
-                trace!("LABEL: exported flag set for station '{}'.", label);
+            2 => {
+                trace!("CROSS: {}.", point);
+                Item::Cross(point)
             }
-            if flags & 0x10 != 0 {
-                station.borrow_mut().fixed = true;
-                trace!("LABEL: fixed flag set for station '{}'.", label);
+            3 => {
+                let flags;
+                unsafe {
+                    match label_from_ptr((*pimg).label) {
+                        Ok(label) => self.label = label,
+                        Err(err) => return Some(Err(err)),
+                    }
+                    flags = (*pimg).flags & 0x7f;
+                }
+                trace!("LABEL: {} -> {}.", point, self.label);
+                Item::Label {
+                    label: self.label.clone(),
+                    coords: point,
+                    flags: StationFlags::from(flags),
+                    // The flags have been masked to seven bits, so always fit in a u8.
+                    raw_flags: flags as u8,
+                }
+            }
+            4 => {
+                let mut lrud = LRUD {
+                    unit: self.units,
+                    ..Default::default()
+                };
+                unsafe {
+                    lrud.update((*pimg).l, (*pimg).r, (*pimg).u, (*pimg).d);
+
+                    // If 0x20 flag is set, do *not* update the label buffer, and instead use the
+                    // previous label.
+                    if (*pimg).flags & 0x20 == 0 {
+                        match label_from_ptr((*pimg).label) {
+                            Ok(label) => self.label = label,
+                            Err(err) => return Some(Err(err)),
+                        }
+                        trace!("XSECT: label set to '{}'.", self.label);
+                    } else {
+                        trace!("XSECT: label not from {}.", self.label);
+                    }
+                }
+                trace!("XSECT: {} for {}.", lrud, self.label);
+                Item::Xsect {
+                    label: self.label.clone(),
+                    lrud,
+                }
             }
-            if flags & 0x20 != 0 {
-                // Anonymous stations are given a UUID as their label
-                station.borrow_mut().anonymous = true;
-                trace!("LABEL: anonymous flag set for station '{}'.", label);
-                station.borrow_mut().label = Uuid::new_v4().to_string();
+            5 => {
+                trace!("XSECT_END command received.");
+                Item::XsectEnd
+            }
+            6 => {
+                let loop_error = unsafe {
+                    LoopError {
+                        n_legs: usize::try_from((*pimg).n_legs).unwrap_or(0),
+                        length: (*pimg).length,
+                        error: (*pimg).E,
+                        horizontal_error: (*pimg).H,
+                        vertical_error: (*pimg).V,
+                    }
+                };
                 trace!(
-                    "LABEL: UUID '{}' set for anonymous station.",
-                    station.borrow().label,
+                    "ERROR_INFO: error {} for {} legs.",
+                    loop_error.error,
+                    loop_error.n_legs
                 );
+                Item::ErrorInfo(loop_error)
+            }
+            _ => {
+                trace!("Unknown item type {} received.", result);
+                return Some(Err(ReadError::UnknownItem(result)));
             }
-            if flags & 0x40 != 0 {
-                station.borrow_mut().wall = true;
-                trace!("LABEL: wall flag set for station '{}'.", label);
+        };
+        Some(Ok(item))
+    }
+}
+
+impl Iterator for SurveyReader {
+    type Item = Result<Item, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let item = self.read_item();
+        if !matches!(item, Some(Ok(_))) {
+            self.finished = true;
+        }
+        item
+    }
+}
+
+impl Drop for SurveyReader {
+    fn drop(&mut self) {
+        unsafe {
+            survex::img_close(self.pimg);
+        }
+    }
+}
+
+/// Read all of the items from an open Survex file and build a [`SurveyData`] instance from them.
+/// `progress` is called with the running count of items read, as described in
+/// [`load_from_path_with_progress`].
+fn read_survey(
+    reader: SurveyReader,
+    options: LoadOptions,
+    progress: &mut dyn FnMut(usize),
+) -> Result<SurveyData, ReadError> {
+    // Create an SurveyData instance to store and update data as it is read.
+    let mut data = SurveyData::new();
+    data.title = reader.title.clone();
+    data.coordinate_system = reader.coordinate_system.clone();
+    data.datestamp = reader.datestamp.clone();
+    data.timestamp = reader.timestamp;
+    data.format_version = reader.format_version;
+    data.extended_elevation = reader.extended_elevation;
+    data.units = reader.units;
+    data.separator = reader.separator;
+
+    // The way Survex 3D file reading works is that it will first spit out a bunch of coordinates
+    // and centrelines (determined by MOVE and LINE) commands, and it will then later give names
+    // to those coordinates by means of a LABEL command. As such, we will store the connections
+    // between two coordinates in a vector and then later - once we have read the full .3d file and
+    // have labels for all sets of coordinates - add the connections to the graph.
+    let mut connections = Vec::new();
+    let mut items = 0;
+    let mut anonymous = 0;
+    let adjust = |p: Point| {
+        if options.z_down {
+            Point::new(p.x, p.y, -p.z)
+        } else {
+            p
+        }
+    };
+
+    trace!("Reading Survex file in load_from_path function.");
+    for item in reader {
+        let item = item?;
+        items += 1;
+        if items % PROGRESS_INTERVAL == 0 {
+            progress(items);
+        }
+
+        match item {
+            Item::Line {
+                from,
+                to,
+                style,
+                flags,
+                days,
+            } => {
+                // The previous position will have been set by a previous MOVE or LINE item, so
+                // we can create a connection between the previous station and the current
+                // station. After the 3d file has been read, we can use the connections vector
+                // to add the connections to the graph.
+                let (from, to) = (adjust(from), adjust(to));
+                let mut leg = Leg::new(from.distance(&to));
+                leg.style = style;
+                leg.flags = flags;
+                leg.days = days;
+                connections.push((from, to, leg));
             }
-        } else if result == 4 {
-            // XSECT command
-            let (l, r, u, d, flags);
-            unsafe {
-                l = (*pimg).l;
-                r = (*pimg).r;
-                u = (*pimg).u;
-                d = (*pimg).d;
-                flags = (*pimg).flags & 0x7f;
-
-                // If 0x20 flag is set, do *not* update the label buffer, and instead use the
-                // previous label.
-                if flags & 0x20 == 0 {
-                    label = CStr::from_ptr((*pimg).label).to_str().unwrap();
-                    trace!("XSECT: label set to '{}'.", label);
+            Item::Label {
+                label,
+                coords,
+                flags,
+                raw_flags,
+            } => {
+                let coords = adjust(coords);
+                let (station, index) = if options.detect_duplicate_labels {
+                    data.add_or_record_conflict(coords, &label)
                 } else {
-                    trace!("XSECT: label not from {}.", label);
+                    data.add_or_update(coords, &label)
+                };
+
+                // Set the flags for the station
+                {
+                    let mut station = station.borrow_mut();
+                    station.raw_flags |= raw_flags;
+                    station.flags |= flags;
+                }
+                trace!("LABEL: flags {:?} set for station '{}'.", flags, label);
+                if flags.contains(StationFlags::ANONYMOUS) {
+                    // Anonymous stations are given a UUID as their label, derived from their
+                    // position in the file and their coordinates so that it is the same each time
+                    // the file is loaded.
+                    let name = format!("{}:{}", anonymous, coords);
+                    let uuid = Uuid::new_v5(&ANONYMOUS_NAMESPACE, name.as_bytes());
+                    anonymous += 1;
+                    station.borrow_mut().original_label = Some(label);
+                    data.relabel(index, &uuid.to_string());
+                    trace!(
+                        "LABEL: UUID '{}' set for anonymous station.",
+                        station.borrow().label,
+                    );
                 }
             }
-            trace!("XSECT: l={}, r={}, u={}, d={} for {}.", l, r, u, d, label);
-            data.get_by_label(label)
-                .unwrap_or_else(|| panic!("Could not find station with label {:?}", label))
-                .borrow_mut()
-                .lrud
-                .update(l, r, u, d);
-        } else if result == 5 {
-            // XSECT_END command
-            trace!("XSECT_END command received. Ignoring.");
-        } else if result == 6 {
-            // ERROR_INFO command
-            trace!("ERROR_INFO command received. Ignoring.");
-        } else {
-            panic!("Unknown item type in Survex file");
+            Item::Xsect { label, lrud } => {
+                data.get_by_label(&label)
+                    .ok_or(ReadError::MissingStation(label))?
+                    .borrow_mut()
+                    .lrud = lrud;
+            }
+            Item::ErrorInfo(loop_error) => {
+                // The error information applies to the traverse made up of the preceding n_legs
+                // LINE items, so update the error for each of those connections.
+                let start = connections.len().saturating_sub(loop_error.n_legs);
+                for (_, _, leg) in connections[start..].iter_mut() {
+                    leg.error = Some(loop_error.error);
+                }
+                data.loop_errors.push(loop_error);
+            }
+            Item::Move(_) | Item::Cross(_) | Item::XsectEnd => {}
         }
     }
 
+    progress(items);
     trace!(
         "Survex file reading complete. Processed {} stations and {} connections.",
         data.stations.len(),
@@ -189,23 +724,10 @@ pub fn load_from_path(path: PathBuf) -> Result<SurveyData, Box<dyn Error>> {
 
     // Survex file reading is complete. We now need to iterate over the connections vector and
     // add the connections to the graph by looking up the node index for each station and adding
-    // an edge between them with the distance between the two stations as the weight.
-    for (p1, p2) in connections.iter() {
-        let from_station_node_index = data
-            .get_by_coords(p1)
-            .unwrap_or_else(|| panic!("Could not find station with coordinates {:?}", p1))
-            .borrow()
-            .index;
-        let to_station_node_index = data
-            .get_by_coords(p2)
-            .unwrap_or_else(|| panic!("Could not find station with coordinates {:?}", p2))
-            .borrow()
-            .index;
-        data.graph.add_edge(
-            from_station_node_index,
-            to_station_node_index,
-            p1.distance(p2),
-        );
+    // an edge between them with the leg as the weight.
+    let index = coordinate_index(&data);
+    for (from, to, leg) in resolve_connections(&index, &connections)? {
+        data.graph.add_edge(from, to, leg);
     }
 
     trace!(
@@ -217,9 +739,185 @@ pub fn load_from_path(path: PathBuf) -> Result<SurveyData, Box<dyn Error>> {
     Ok(data)
 }
 
+/// Build an index of station coordinates to their index in the graph, so that the stations at
+/// either end of each leg can be found without searching every station. Where several stations
+/// share the same coordinates, the first is used, as with [`SurveyData::get_by_coords`].
+fn coordinate_index(data: &SurveyData) -> HashMap<[u64; 3], NodeIndex> {
+    let mut index = HashMap::with_capacity(data.stations.len());
+    for station in data.iter_stations() {
+        index
+            .entry(coordinate_key(&station.coords))
+            .or_insert(station.index);
+    }
+    index
+}
+
+/// Convert a [`Point`] into a key for a [`coordinate_index`] or any other map of positions.
+/// Points which compare as equal have the same key, as zero is always stored as positive zero.
+pub(crate) fn coordinate_key(p: &Point) -> [u64; 3] {
+    [
+        (p.x + 0.0).to_bits(),
+        (p.y + 0.0).to_bits(),
+        (p.z + 0.0).to_bits(),
+    ]
+}
+
+/// Look up the stations at either end of each connection in a [`coordinate_index`], returning the
+/// legs to be added to the graph in the same order as the connections. If the `rayon` feature is
+/// enabled, the connections are resolved in parallel, which gives the same result.
+fn resolve_connections(
+    index: &HashMap<[u64; 3], NodeIndex>,
+    connections: &[(Point, Point, Leg)],
+) -> Result<Vec<(NodeIndex, NodeIndex, Leg)>, ReadError> {
+    let resolve = |(from, to, leg): &(Point, Point, Leg)| {
+        let station = |p: &Point| {
+            index
+                .get(&coordinate_key(p))
+                .copied()
+                .ok_or_else(|| ReadError::MissingStation(p.to_string()))
+        };
+        Ok((station(from)?, station(to)?, *leg))
+    };
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        connections.par_iter().map(resolve).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        connections.iter().map(resolve).collect()
+    }
+}
+
+/// Check that a file exists and can be opened for reading, returning the error reported by the
+/// operating system if not. Directories are reported as [`io::ErrorKind::IsADirectory`].
+fn check_readable(path: &Path) -> io::Result<()> {
+    let file = fs::File::open(path)?;
+    if file.metadata()?.is_dir() {
+        return Err(io::Error::from(io::ErrorKind::IsADirectory));
+    }
+    Ok(())
+}
+
+/// Convert a label returned by the Survex library into a [`String`], returning an error if the
+/// label is not valid UTF-8.
+///
+/// # Safety
+/// `ptr` must point to a valid NUL-terminated string.
+unsafe fn label_from_ptr(ptr: *const c_char) -> Result<String, ReadError> {
+    let label = CStr::from_ptr(ptr);
+    label
+        .to_str()
+        .map(String::from)
+        .map_err(|_| ReadError::InvalidLabel(label.to_bytes().to_vec()))
+}
+
+/// Determine the unit of the coordinates in a Survex file from its coordinate system. The Survex
+/// library gives coordinates in the units of the coordinate system, which are metres unless a
+/// PROJ string specifies feet with `+units=ft` or `+units=us-ft`. Coordinate systems given as a
+/// code, such as `EPSG:2272`, can not be checked without PROJ and are assumed to be in metres.
+fn units_from_coordinate_system(coordinate_system: Option<&str>) -> LengthUnit {
+    let feet = coordinate_system.is_some_and(|cs| {
+        cs.split_whitespace()
+            .any(|param| param == "+units=ft" || param == "+units=us-ft")
+    });
+    if feet {
+        trace!("Coordinate system specifies feet, coordinates are in feet.");
+        LengthUnit::Feet
+    } else {
+        trace!("No unit specified by the coordinate system, assuming metres.");
+        LengthUnit::Metres
+    }
+}
+
+/// Convert an optional string returned by the Survex library into a [`String`]. Returns [`None`]
+/// if the pointer is null or the string is empty. Invalid UTF-8 is replaced rather than treated
+/// as an error, as these strings are descriptive rather than used to identify stations.
+///
+/// # Safety
+/// `ptr` must either be null or point to a valid NUL-terminated string.
+unsafe fn optional_string_from_ptr(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(ptr).to_string_lossy();
+    (!string.is_empty()).then(|| string.into_owned())
+}
+
+/// Convert a numeric datestamp from the Survex library, given as seconds since the Unix epoch,
+/// into a [`SystemTime`]. The library uses -1 to indicate that the time is not available.
+fn timestamp_from_numeric(seconds: i64) -> Option<SystemTime> {
+    if seconds == -1 {
+        return None;
+    }
+    let offset = Duration::from_secs(seconds.unsigned_abs());
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+/// Convert the survey dates of a leg from the Survex library, given as seconds since the Unix
+/// epoch, into Survex day numbers. The library uses 0 to indicate that the leg has no date.
+fn days_from_dates(date1: i64, date2: i64) -> Option<(i32, i32)> {
+    if date1 == 0 {
+        return None;
+    }
+    let days = |date: i64| i32::try_from(date.div_euclid(86400) + UNIX_EPOCH_DAYS).ok();
+    Some((days(date1)?, days(date2)?))
+}
+
+/// Create a single [`SurveyData`] instance from a project made up of several Survex files.
+///
+/// The manifest at `path` is a plain text file listing one Survex file per line. Relative paths
+/// are resolved relative to the directory containing the manifest. Blank lines and lines starting
+/// with `#` are ignored. Each file is read with [`load_from_path`][`crate::read::load_from_path`]
+/// and the results are combined with [`SurveyData::merge`], which welds together stations with
+/// the same label in different files.
+///
+/// The title, coordinate system and other header information are taken from the first file. The
+/// coordinates of every file must be comparable, so a [`ReadError::Incompatible`] error is
+/// returned if a later file has a different coordinate system, units or label separator to the
+/// first. An empty manifest gives an empty [`SurveyData`] instance.
+pub fn load_from_manifest(path: PathBuf) -> Result<SurveyData, ReadError> {
+    let manifest = fs::read_to_string(&path)?;
+    let base = path.parent().map(PathBuf::from).unwrap_or_default();
+
+    let mut data: Option<SurveyData> = None;
+    for line in manifest.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        trace!("Loading '{}' from manifest '{:?}'.", line, path);
+        let file = base.join(line);
+        let other = load_from_path(file.clone())?;
+        let Some(data) = data.as_mut() else {
+            data = Some(other);
+            continue;
+        };
+
+        let incompatible = |property: &str| ReadError::Incompatible(file.clone(), property.into());
+        if other.coordinate_system != data.coordinate_system {
+            return Err(incompatible("coordinate system"));
+        }
+        if other.units != data.units {
+            return Err(incompatible("units"));
+        }
+        if other.separator != data.separator {
+            return Err(incompatible("separator"));
+        }
+        data.merge(other, LabelCollision::Weld);
+    }
+    Ok(data.unwrap_or_default())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builder::SurveyBuilder;
+    use crate::write::save_to_path;
 
     #[test]
     fn load_file() {
@@ -233,6 +931,52 @@ mod tests {
         assert!(load_from_path(path).is_err());
     }
 
+    #[test]
+    fn test_read_errors() {
+        let path = PathBuf::from("tests/data/this-file-does-not-exist.3d");
+        let result = load_from_path(path.clone());
+        assert!(matches!(result, Err(ReadError::NotFound(p)) if p == path));
+
+        let path = PathBuf::from("tests/data");
+        let result = load_from_path(path);
+        assert!(matches!(
+            result,
+            Err(ReadError::Unreadable(_, err)) if err.kind() == io::ErrorKind::IsADirectory
+        ));
+
+        let path = PathBuf::from("tests/data/truncated.3d");
+        assert!(matches!(load_from_path(path), Err(ReadError::BadData)));
+
+        let path = PathBuf::from("tests/data/nottsii.3d\0.bak");
+        let result = load_from_path(path.clone());
+        assert!(matches!(result, Err(ReadError::InvalidPath(p)) if p == path));
+    }
+
+    #[test]
+    fn test_label_from_ptr() {
+        let label = CString::new("nottsii.entrance").unwrap();
+        let result = unsafe { label_from_ptr(label.as_ptr()) };
+        assert_eq!(result.unwrap(), "nottsii.entrance");
+
+        let label = CString::new(vec![b'c', b'a', b'v', 0xE9]).unwrap();
+        let result = unsafe { label_from_ptr(label.as_ptr()) };
+        match result {
+            Err(ReadError::InvalidLabel(bytes)) => assert_eq!(bytes, vec![b'c', b'a', b'v', 0xE9]),
+            _ => panic!("Expected an InvalidLabel error"),
+        }
+    }
+
+    /// Labels which are not valid UTF-8, such as the Latin-1 'é' in this file, should be reported
+    /// as an error rather than causing a panic.
+    #[test]
+    fn test_non_utf8_label() {
+        let path = PathBuf::from("tests/data/latin1.pos");
+        match load_from_path(path) {
+            Err(ReadError::InvalidLabel(bytes)) => assert_eq!(bytes, b"grotte.entr\xe9e"),
+            _ => panic!("Expected an InvalidLabel error"),
+        }
+    }
+
     /// Check that the correct number of stations are generated from the 3d file. The verification
     /// values were created by checking how many NODE lines were generated when running the same 3d
     /// file through Survex `dump3d`.
@@ -272,6 +1016,7 @@ mod tests {
         assert_eq!(station.lrud.right, None);
         assert_eq!(station.lrud.up, None);
         assert_eq!(station.lrud.down, Some(9.0));
+        assert_eq!(station.lrud.unit, LengthUnit::Metres);
     }
 
     #[test]
@@ -289,53 +1034,509 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_flags_are_set_correctly() {
         let path = PathBuf::from("tests/data/nottsii.3d");
         let manager = load_from_path(path).unwrap();
         let station = manager.get_by_label("nottsii.entrance").unwrap();
         let station = station.borrow();
-        assert_eq!(station.surface, false);
-        assert_eq!(station.underground, false);
-        assert_eq!(station.entrance, true);
-        assert_eq!(station.exported, true);
-        assert_eq!(station.fixed, true);
-        assert_eq!(station.anonymous, false);
-        assert_eq!(station.wall, false);
+        assert_eq!(station.surface(), false);
+        assert_eq!(station.underground(), false);
+        assert_eq!(station.entrance(), true);
+        assert_eq!(station.exported(), true);
+        assert_eq!(station.fixed(), true);
+        assert_eq!(station.anonymous(), false);
+        assert_eq!(station.wall(), false);
 
         let station = manager
             .get_by_label("nottsii.inlet5.inlet5-resurvey-2.3.17")
             .unwrap();
         let station = station.borrow();
-        assert_eq!(station.surface, false);
-        assert_eq!(station.underground, true);
-        assert_eq!(station.entrance, false);
-        assert_eq!(station.exported, true);
-        assert_eq!(station.fixed, false);
-        assert_eq!(station.anonymous, false);
-        assert_eq!(station.wall, false);
+        assert_eq!(station.surface(), false);
+        assert_eq!(station.underground(), true);
+        assert_eq!(station.entrance(), false);
+        assert_eq!(station.exported(), true);
+        assert_eq!(station.fixed(), false);
+        assert_eq!(station.anonymous(), false);
+        assert_eq!(station.wall(), false);
 
         let station = manager
             .get_by_label("nottsii.mainstreamway.mainstreamway3.27")
             .unwrap();
         let station = station.borrow();
-        assert_eq!(station.surface, false);
-        assert_eq!(station.underground, true);
-        assert_eq!(station.entrance, false);
-        assert_eq!(station.exported, false);
-        assert_eq!(station.fixed, false);
-        assert_eq!(station.anonymous, false);
-        assert_eq!(station.wall, false);
+        assert_eq!(station.surface(), false);
+        assert_eq!(station.underground(), true);
+        assert_eq!(station.entrance(), false);
+        assert_eq!(station.exported(), false);
+        assert_eq!(station.fixed(), false);
+        assert_eq!(station.anonymous(), false);
+        assert_eq!(station.wall(), false);
 
         let station = manager
             .get_by_label("nottsii.countlazloall.thecupcake.009")
             .unwrap();
         let station = station.borrow();
-        assert_eq!(station.surface, true);
-        assert_eq!(station.underground, false);
-        assert_eq!(station.entrance, false);
-        assert_eq!(station.exported, false);
-        assert_eq!(station.fixed, false);
-        assert_eq!(station.anonymous, false);
-        assert_eq!(station.wall, false);
+        assert_eq!(station.surface(), true);
+        assert_eq!(station.underground(), false);
+        assert_eq!(station.entrance(), false);
+        assert_eq!(station.exported(), false);
+        assert_eq!(station.fixed(), false);
+        assert_eq!(station.anonymous(), false);
+        assert_eq!(station.wall(), false);
+    }
+
+    #[test]
+    fn test_raw_flags() {
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let data = load_from_path(path).unwrap();
+        let station = data.get_by_label("nottsii.entrance").unwrap();
+        assert_eq!(station.borrow().raw_flags, 0x04 | 0x08 | 0x10);
+        let station = data
+            .get_by_label("nottsii.countlazloall.thecupcake.009")
+            .unwrap();
+        assert_eq!(station.borrow().raw_flags, 0x01);
+
+        for station in data.iter_stations() {
+            let raw = i32::from(station.raw_flags);
+            assert_eq!(StationFlags::from(raw), station.flags);
+            assert_eq!(raw & 0x01 != 0, station.surface());
+            assert_eq!(raw & 0x02 != 0, station.underground());
+            assert_eq!(raw & 0x04 != 0, station.entrance());
+            assert_eq!(raw & 0x08 != 0, station.exported());
+            assert_eq!(raw & 0x10 != 0, station.fixed());
+            assert_eq!(raw & 0x20 != 0, station.anonymous());
+            assert_eq!(raw & 0x40 != 0, station.wall());
+        }
+    }
+
+    #[test]
+    fn test_detect_duplicate_labels() {
+        let options = LoadOptions {
+            detect_duplicate_labels: true,
+            ..Default::default()
+        };
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path_with_options(path, options).unwrap();
+        assert!(manager.label_conflicts.is_empty());
+        assert_eq!(manager.stations.len(), 1904);
+        assert_eq!(manager.graph.edge_count(), 1782);
+    }
+
+    #[test]
+    fn test_leg_styles_are_set_correctly() {
+        let path = PathBuf::from("tests/data/0733.3d");
+        let manager = load_from_path(path).unwrap();
+        let count = |style| {
+            manager
+                .graph
+                .edge_weights()
+                .filter(|leg| leg.style == style)
+                .count()
+        };
+        assert_eq!(count(LegStyle::Normal), 5906);
+        assert_eq!(count(LegStyle::Diving), 23);
+    }
+
+    #[test]
+    fn test_z_down() {
+        let options = LoadOptions {
+            z_down: true,
+            ..Default::default()
+        };
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path_with_options(path, options).unwrap();
+        let station = manager.get_by_label("nottsii.entrance").unwrap();
+        assert_eq!(
+            station.borrow().coords,
+            Point::new(66668.0, 78303.0, -319.0)
+        );
+        assert_eq!(manager.stations.len(), 1904);
+        assert_eq!(manager.graph.edge_count(), 1782);
+    }
+
+    #[test]
+    fn test_load_from_manifest() {
+        let cs = "+proj=tmerc +lat_0=38 +lon_0=-78.5 +k=0.99995 +x_0=304800 +y_0=0 +ellps=GRS80 \
+                  +units=us-ft +no_defs";
+        let survey = |labels: [&str; 2], cs: &str| {
+            let mut data = SurveyBuilder::new()
+                .title("Project")
+                .add_station(labels[0], Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+                .add_station(
+                    labels[1],
+                    Point::new(30.0, 40.0, 0.0),
+                    StationFlags::empty(),
+                )
+                .add_leg(labels[0], labels[1])
+                .build()
+                .unwrap();
+            data.coordinate_system = Some(String::from(cs));
+            data
+        };
+        let dir = tempfile::tempdir().unwrap();
+        save_to_path(&survey(["a.1", "a.2"], cs), dir.path().join("a.3d")).unwrap();
+        save_to_path(&survey(["a.2", "b.1"], cs), dir.path().join("b.3d")).unwrap();
+        save_to_path(
+            &survey(["c.1", "c.2"], "EPSG:27700"),
+            dir.path().join("c.3d"),
+        )
+        .unwrap();
+
+        let path = dir.path().join("manifest.txt");
+        fs::write(&path, "# Project\na.3d\n\nb.3d\n").unwrap();
+        let data = load_from_manifest(path.clone()).unwrap();
+        assert_eq!(data.title, "Project");
+        assert_eq!(data.coordinate_system.as_deref(), Some(cs));
+        assert_eq!(data.units, LengthUnit::Feet);
+        assert_eq!(data.stations.len(), 3);
+        assert_eq!(data.graph.edge_count(), 2);
+
+        fs::write(&path, "a.3d\nc.3d\n").unwrap();
+        let result = load_from_manifest(path);
+        assert!(matches!(
+            result,
+            Err(ReadError::Incompatible(file, property))
+                if file == dir.path().join("c.3d") && property == "coordinate system"
+        ));
+
+        // The files in the test manifest use different coordinate systems.
+        let path = PathBuf::from("tests/data/manifest.txt");
+        let result = load_from_manifest(path);
+        assert!(matches!(result, Err(ReadError::Incompatible(..))));
+
+        let path = PathBuf::from("tests/data/this-manifest-does-not-exist.txt");
+        assert!(load_from_manifest(path).is_err());
+    }
+
+    #[test]
+    fn test_load_from_file() {
+        let file = fs::File::open("tests/data/0733.3d").unwrap();
+        let data = load_from_file(file).unwrap();
+        assert_eq!(data.stations.len(), 6104);
+        assert_eq!(data.graph.edge_count(), 5929);
+
+        // A file which has been partly read is copied from its current position.
+        let mut file = fs::File::open("tests/data/0733.3d").unwrap();
+        io::Read::read_exact(&mut file, &mut [0; 4]).unwrap();
+        assert!(matches!(load_from_file(file), Err(ReadError::BadFormat)));
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        for path in ["tests/data/nottsii.3d", "tests/data/0733.3d"] {
+            let metadata = read_metadata(PathBuf::from(path)).unwrap();
+            let data = load_from_path(PathBuf::from(path)).unwrap();
+            assert_eq!(metadata.title, data.title);
+            assert_eq!(metadata.coordinate_system, data.coordinate_system);
+            assert_eq!(metadata.datestamp.as_deref(), data.datestamp());
+            assert_eq!(metadata.timestamp, data.timestamp());
+            assert_eq!(metadata.separator, data.separator());
+            assert_eq!(metadata.stations, data.stations.len());
+            assert_eq!(metadata.legs, data.graph.edge_count());
+            assert_eq!(metadata.bounding_box, data.bounding_box());
+        }
+
+        let path = PathBuf::from("tests/data/this-file-does-not-exist.3d");
+        assert!(matches!(read_metadata(path), Err(ReadError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_load_from_bytes() {
+        let bytes = fs::read("tests/data/0733.3d").unwrap();
+        let manager = load_from_bytes(&bytes).unwrap();
+        assert_eq!(manager.stations.len(), 6104);
+        assert_eq!(manager.graph.edge_count(), 5929);
+
+        let result = load_from_bytes(b"this is not a survex file");
+        assert!(matches!(result, Err(ReadError::BadFormat)));
+    }
+
+    #[test]
+    fn test_load_pos_file() {
+        let path = PathBuf::from("tests/data/mainstreamway.pos");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.stations.len(), 171);
+        assert_eq!(manager.graph.edge_count(), 0);
+        assert_eq!(manager.format_version(), -1);
+
+        let station = manager
+            .get_by_label("nottsii.mainstreamway.dome-jimlet.1")
+            .unwrap();
+        assert_eq!(
+            station.borrow().coords,
+            Point::new(66782.01, 78224.70, 259.76)
+        );
+        assert!(station.borrow().underground());
+
+        // The format is detected from the contents when loading from memory
+        let bytes = fs::read("tests/data/mainstreamway.pos").unwrap();
+        let manager = load_from_bytes(&bytes).unwrap();
+        assert_eq!(manager.stations.len(), 171);
+    }
+
+    #[test]
+    fn test_load_from_path_with_progress() {
+        let mut calls = Vec::new();
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path_with_progress(path, |items| calls.push(items)).unwrap();
+        assert_eq!(manager.stations.len(), 1904);
+
+        // Calls are made at every interval and once more at the end with the final count
+        let last = *calls.last().unwrap();
+        assert!(last >= manager.stations.len() + manager.graph.edge_count());
+        let (intervals, _) = calls.split_at(calls.len() - 1);
+        assert_eq!(intervals.len(), last / PROGRESS_INTERVAL);
+        for (i, items) in intervals.iter().enumerate() {
+            assert_eq!(*items, (i + 1) * PROGRESS_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn test_load_survey_from_path() {
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let survey = load_survey_from_path(path.clone(), "nottsii.mainstreamway").unwrap();
+        let full = load_from_path(path.clone()).unwrap();
+        let filtered = full.subsurvey("nottsii.mainstreamway.");
+
+        assert_eq!(survey.stations.len(), filtered.stations.len());
+        assert_eq!(survey.title, "mainstreamway");
+
+        let station = survey.get_by_label("dome-jimlet.1").unwrap();
+        let original = full
+            .get_by_label("nottsii.mainstreamway.dome-jimlet.1")
+            .unwrap();
+        assert_eq!(station.borrow().coords, original.borrow().coords);
+
+        let survey = load_survey_from_path(path.clone(), "nottsii.nosuchsurvey").unwrap();
+        assert_eq!(survey.stations.len(), 0);
+        assert!(matches!(
+            load_survey_from_path(path, "nottsii\0"),
+            Err(ReadError::InvalidSurvey(_))
+        ));
+    }
+
+    #[test]
+    fn test_anonymous_stations() {
+        let path = PathBuf::from("tests/data/anonymous.pos");
+        let first = load_from_path(path.clone()).unwrap();
+        let second = load_from_path(path).unwrap();
+        assert_eq!(first.stations.len(), 5);
+
+        let anonymous = |data: &SurveyData| {
+            data.stations
+                .iter()
+                .filter(|station| station.borrow().anonymous())
+                .map(|station| station.borrow().label.clone())
+                .collect::<Vec<_>>()
+        };
+        let labels = anonymous(&first);
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels, anonymous(&second));
+        assert!(labels.iter().all(|label| Uuid::parse_str(label).is_ok()));
+        assert!(labels[1..].iter().all(|label| label != &labels[0]));
+
+        let station = first.get_by_label(&labels[0]).unwrap();
+        let station = station.borrow();
+        assert_eq!(station.coords, Point::new(101.5, 202.25, 49.1));
+        assert_eq!(station.original_label.as_deref(), Some(""));
+
+        let station = first.get_by_label("cave.1").unwrap();
+        assert!(!station.borrow().anonymous());
+        assert_eq!(station.borrow().original_label, None);
+    }
+
+    #[test]
+    fn test_extended_elevation() {
+        let data = load_from_path(PathBuf::from("tests/data/extended.3d")).unwrap();
+        assert!(data.is_extended_elevation());
+        assert_eq!(data.stations.len(), 3);
+        assert_eq!(data.graph.edge_count(), 2);
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert!(!data.is_extended_elevation());
+    }
+
+    #[test]
+    fn test_survey_reader() {
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let reader = SurveyReader::open(path.clone()).unwrap();
+        assert_eq!(reader.title(), "NottsIIWithEntrance");
+        let mut counts = HashMap::new();
+        for item in reader {
+            let variant = match item.unwrap() {
+                Item::Move(_) => "move",
+                Item::Line { .. } => "line",
+                Item::Cross(_) => "cross",
+                Item::Label { .. } => "label",
+                Item::Xsect { .. } => "xsect",
+                Item::XsectEnd => "xsect_end",
+                Item::ErrorInfo(_) => "error_info",
+            };
+            *counts.entry(variant).or_insert(0) += 1;
+        }
+
+        let data = load_from_path(path).unwrap();
+        assert_eq!(counts["line"], data.graph.edge_count());
+        assert_eq!(counts["error_info"], data.loop_errors.len());
+        assert!(counts["move"] > 0);
+        assert!(counts["label"] >= data.stations.len());
+        assert!(!counts.contains_key("cross"));
+    }
+
+    #[test]
+    fn test_units() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        assert_eq!(data.units, LengthUnit::Metres);
+
+        let mut data = SurveyBuilder::new()
+            .add_station("a.1", Point::new(0.0, 0.0, 0.0), StationFlags::empty())
+            .add_station("a.2", Point::new(30.0, 40.0, 0.0), StationFlags::empty())
+            .add_leg("a.1", "a.2")
+            .build()
+            .unwrap();
+        data.coordinate_system = Some(String::from(
+            "+proj=tmerc +lat_0=38 +lon_0=-78.5 +k=0.99995 +x_0=304800 +y_0=0 +ellps=GRS80 \
+             +units=us-ft +no_defs",
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feet.3d");
+        save_to_path(&data, path.clone()).unwrap();
+        let data = load_from_path(path).unwrap();
+        assert_eq!(data.units, LengthUnit::Feet);
+        let length = LengthUnit::Feet.convert(data.total_length(true, true), LengthUnit::Metres);
+        assert!((length - 15.24).abs() < 1e-6);
+
+        assert_eq!(units_from_coordinate_system(None), LengthUnit::Metres);
+        assert_eq!(
+            units_from_coordinate_system(Some("EPSG:27700")),
+            LengthUnit::Metres
+        );
+        assert_eq!(
+            units_from_coordinate_system(Some("+proj=utm +zone=17 +units=ft")),
+            LengthUnit::Feet
+        );
+    }
+
+    #[test]
+    fn test_leg_dates() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let undated = data
+            .graph
+            .edge_weights()
+            .filter(|leg| leg.days.is_none())
+            .count();
+        assert_eq!(undated, 88);
+        let leg = data
+            .graph
+            .edge_weights()
+            .find(|leg| leg.days == Some((32688, 32749)))
+            .unwrap();
+        let (first, last) = leg.date_range().unwrap();
+        assert_eq!(first.to_string(), "1989-07-01");
+        assert_eq!(last.to_string(), "1989-08-31");
+
+        assert_eq!(days_from_dates(0, 0), None);
+        assert_eq!(days_from_dates(799372800, 799372800), Some((34819, 34819)));
+        assert_eq!(days_from_dates(-86400, 0), Some((25566, 25567)));
+    }
+
+    #[test]
+    fn test_resolve_connections() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let connections = data
+            .iter_legs()
+            .map(|(from, to, length)| {
+                let (from, to) = (from.borrow().coords, to.borrow().coords);
+                (from, to, Leg::new(length))
+            })
+            .collect::<Vec<_>>();
+
+        // Resolve the connections one at a time with get_by_coords, as a reference for the
+        // indexed (and, with the rayon feature, parallel) implementation.
+        let serial = connections
+            .iter()
+            .map(|(from, to, leg)| {
+                let from = data.get_by_coords(from).unwrap().borrow().index;
+                let to = data.get_by_coords(to).unwrap().borrow().index;
+                (from, to, *leg)
+            })
+            .collect::<Vec<_>>();
+        let index = coordinate_index(&data);
+        let resolved = resolve_connections(&index, &connections).unwrap();
+        assert_eq!(resolved.len(), data.graph.edge_count());
+        assert_eq!(resolved, serial);
+
+        let missing = [(
+            Point::new(0.5, 0.5, 0.5),
+            Point::new(0.0, 0.0, 0.0),
+            Leg::new(1.0),
+        )];
+        assert!(matches!(
+            resolve_connections(&index, &missing),
+            Err(ReadError::MissingStation(_))
+        ));
+    }
+
+    #[test]
+    fn test_title() {
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.title, "NottsIIWithEntrance");
+
+        let path = PathBuf::from("tests/data/0733.3d");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.title, "0733");
+    }
+
+    #[test]
+    fn test_optional_string_from_ptr() {
+        assert_eq!(unsafe { optional_string_from_ptr(ptr::null()) }, None);
+
+        let empty = CString::new("").unwrap();
+        assert_eq!(unsafe { optional_string_from_ptr(empty.as_ptr()) }, None);
+
+        let title = CString::new("Title").unwrap();
+        assert_eq!(
+            unsafe { optional_string_from_ptr(title.as_ptr()) },
+            Some(String::from("Title"))
+        );
+    }
+
+    #[test]
+    fn test_coordinate_system() {
+        let path = PathBuf::from("tests/data/0733.3d");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.coordinate_system(), Some("EPSG:32630"));
+
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.coordinate_system(), None);
+    }
+
+    #[test]
+    fn test_datestamp_and_format_version() {
+        let path = PathBuf::from("tests/data/nottsii.3d");
+        let manager = load_from_path(path).unwrap();
+        assert_eq!(manager.datestamp(), Some("@1690876379"));
+        assert_eq!(
+            manager.timestamp(),
+            Some(UNIX_EPOCH + Duration::from_secs(1690876379))
+        );
+        assert_eq!(manager.format_version(), 8);
+        assert_eq!(manager.separator(), '.');
+
+        let path = PathBuf::from("tests/data/0733.3d");
+        let manager = load_from_path(path).unwrap();
+        assert!(!manager.datestamp().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_timestamp_from_numeric() {
+        assert_eq!(timestamp_from_numeric(-1), None);
+        assert_eq!(timestamp_from_numeric(0), Some(UNIX_EPOCH));
+        assert_eq!(
+            timestamp_from_numeric(-60),
+            Some(UNIX_EPOCH - Duration::from_secs(60))
+        );
     }
 }
@@ -0,0 +1,181 @@
+//! A spatial index over station coordinates
+//!
+//! This module contains a simple static k-d tree which is used by
+//! [`SurveyData`][`crate::data::SurveyData`] to answer nearest-station and radius queries without
+//! comparing against every station.
+
+use crate::point::Point;
+
+/// A static k-d tree of points, each associated with an item index
+///
+/// The tree is stored implicitly in a vector: each slice of the vector has its splitting point at
+/// its midpoint, with the points before the midpoint on one side of the splitting plane and the
+/// points after it on the other. The splitting axis cycles through x, y and z with depth.
+#[derive(Debug, Clone)]
+pub(crate) struct KdTree {
+    points: Vec<(Point, usize)>,
+}
+
+/// Retrieve the coordinate of a [`Point`] along the given axis, where 0 is x, 1 is y and 2 is z.
+fn axis_value(point: &Point, axis: usize) -> f64 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+/// Calculate the squared distance between two points, which is cheaper than the distance and
+/// sufficient for comparisons.
+fn distance_squared(a: &Point, b: &Point) -> f64 {
+    (a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)
+}
+
+impl KdTree {
+    /// Build a new [`KdTree`] from the given points and their associated item indices.
+    pub(crate) fn new(mut points: Vec<(Point, usize)>) -> Self {
+        Self::build(&mut points, 0);
+        Self { points }
+    }
+
+    /// The number of points in the tree.
+    pub(crate) fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn build(points: &mut [(Point, usize)], depth: usize) {
+        if points.len() <= 1 {
+            return;
+        }
+        let axis = depth % 3;
+        let mid = points.len() / 2;
+        points.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+            axis_value(a, axis).total_cmp(&axis_value(b, axis))
+        });
+        let (left, right) = points.split_at_mut(mid);
+        Self::build(left, depth + 1);
+        Self::build(&mut right[1..], depth + 1);
+    }
+
+    /// Find the item index of the point nearest to `target`, or [`None`] if the tree is empty.
+    pub(crate) fn nearest(&self, target: &Point) -> Option<usize> {
+        let mut best = None;
+        Self::nearest_in(&self.points, target, 0, &mut best);
+        best.map(|(_, index)| index)
+    }
+
+    fn nearest_in(
+        points: &[(Point, usize)],
+        target: &Point,
+        depth: usize,
+        best: &mut Option<(f64, usize)>,
+    ) {
+        if points.is_empty() {
+            return;
+        }
+        let axis = depth % 3;
+        let mid = points.len() / 2;
+        let (point, index) = &points[mid];
+
+        let distance = distance_squared(point, target);
+        if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            *best = Some((distance, *index));
+        }
+
+        let diff = axis_value(target, axis) - axis_value(point, axis);
+        let (near, far) = if diff < 0.0 {
+            (&points[..mid], &points[mid + 1..])
+        } else {
+            (&points[mid + 1..], &points[..mid])
+        };
+        Self::nearest_in(near, target, depth + 1, best);
+        if best.is_none_or(|(best_distance, _)| diff * diff < best_distance) {
+            Self::nearest_in(far, target, depth + 1, best);
+        }
+    }
+
+    /// Find the item indices of all points within `radius` of `target`, in no particular order.
+    pub(crate) fn within(&self, target: &Point, radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        Self::within_in(&self.points, target, radius, 0, &mut found);
+        found
+    }
+
+    fn within_in(
+        points: &[(Point, usize)],
+        target: &Point,
+        radius: f64,
+        depth: usize,
+        found: &mut Vec<usize>,
+    ) {
+        if points.is_empty() {
+            return;
+        }
+        let axis = depth % 3;
+        let mid = points.len() / 2;
+        let (point, index) = &points[mid];
+
+        if distance_squared(point, target) <= radius * radius {
+            found.push(*index);
+        }
+
+        let diff = axis_value(target, axis) - axis_value(point, axis);
+        if diff - radius <= 0.0 {
+            Self::within_in(&points[..mid], target, radius, depth + 1, found);
+        }
+        if diff + radius >= 0.0 {
+            Self::within_in(&points[mid + 1..], target, radius, depth + 1, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kd_tree_matches_brute_force() {
+        let points = (0..500)
+            .map(|i| {
+                let i = i as f64;
+                let p = Point::new((i * 7.3) % 50.0, (i * 3.1) % 20.0, (i * 1.7) % 9.0);
+                (p, i as usize)
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), 500);
+
+        for target in [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(25.3, 10.1, 4.4),
+            Point::new(-10.0, 40.0, 100.0),
+        ] {
+            let expected = points
+                .iter()
+                .min_by(|(a, _), (b, _)| {
+                    distance_squared(a, &target).total_cmp(&distance_squared(b, &target))
+                })
+                .unwrap();
+            let nearest = tree.nearest(&target).unwrap();
+            assert_eq!(
+                distance_squared(&points[nearest].0, &target),
+                distance_squared(&expected.0, &target)
+            );
+
+            let mut expected = points
+                .iter()
+                .filter(|(p, _)| p.distance(&target) <= 5.0)
+                .map(|(_, i)| *i)
+                .collect::<Vec<_>>();
+            let mut within = tree.within(&target, 5.0);
+            expected.sort();
+            within.sort();
+            assert_eq!(within, expected);
+        }
+
+        assert_eq!(
+            KdTree::new(Vec::new()).nearest(&Point::new(0.0, 0.0, 0.0)),
+            None
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! Data structures to represent survey stations
 
+use crate::point::LengthUnit;
+pub use crate::point::Point;
+use bitflags::bitflags;
 use petgraph::graph::NodeIndex;
 use std::fmt::{Display, Formatter};
 
@@ -9,35 +12,38 @@ use std::fmt::{Display, Formatter};
 /// [`SurveyData`][`crate::data::SurveyData`]. To retrieve a station's connections to other
 /// stations, use the graph provided by the [`SurveyData`][`crate::data::SurveyData`] instance.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Station {
-    /// The name of the survey station. Anonymous stations will be allocated a randomly generated
-    /// name (UUID v4).
+    /// The name of the survey station. Anonymous stations will be allocated a name (UUID v5)
+    /// derived from their position in the Survex file and their coordinates, so the same file
+    /// always gives the same names.
     pub label: String,
+    /// The label which Survex gave an anonymous station before it was allocated a name, which is
+    /// usually empty or the name of the survey containing it. This is [`None`] for all other
+    /// stations.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub original_label: Option<String>,
     /// The coordinates of the survey station.
     pub coords: Point,
-    /// The index of the survey station in the graph.
+    /// The index of the survey station in the graph. When serialized, this is represented by the
+    /// value of [`NodeIndex::index`].
+    #[cfg_attr(feature = "serde", serde(with = "node_index"))]
     pub index: NodeIndex,
     /// The LRUD measurements of the survey station.
     pub lrud: LRUD,
-    /// Whether the survey station is on the surface.
-    pub surface: bool,
-    /// Whether the survey station is underground.
-    pub underground: bool,
-    /// Whether the survey station is an entrance.
-    pub entrance: bool,
-    /// Whether the survey station is exported.
-    pub exported: bool,
-    /// Whether the survey station is fixed.
-    pub fixed: bool,
-    /// Whether the survey station is anonymous.
-    pub anonymous: bool,
-    /// Whether the survey station is a wall.
-    pub wall: bool,
+    /// The flags which Survex recorded for the survey station.
+    pub flags: StationFlags,
+    /// The flags which Survex recorded for the survey station, exactly as given by the Survex
+    /// library (the `img_SFLAG_*` values). Unlike [`flags`][`Station::flags`], this includes any
+    /// flags which are not yet understood by this crate. If a station is listed more than once
+    /// in the file, this contains the flags from all of the listings.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub raw_flags: u8,
 }
 
 impl Station {
-    /// Create a new [`Station`] with the given label, coordinates and index. All flags will
-    /// default to `false` and the [`LRUD`] measurements will default to `None`.
+    /// Create a new [`Station`] with the given label, coordinates and index. No flags will be set
+    /// and the [`LRUD`] measurements will default to `None`.
     ///
     /// You may wish to use a helper function, such as
     /// [`read_from_path`][`crate::read::load_from_path`]
@@ -45,18 +51,107 @@ impl Station {
     pub fn new(label: String, coords: Point, index: NodeIndex) -> Self {
         Self {
             label,
+            original_label: None,
             coords,
             index,
             lrud: LRUD::default(),
-            surface: false,
-            underground: false,
-            entrance: false,
-            exported: false,
-            fixed: false,
-            anonymous: false,
-            wall: false,
+            flags: StationFlags::empty(),
+            raw_flags: 0,
         }
     }
+
+    /// Split the label of the station into its survey path components, using the given
+    /// separator. For example, `cave.upper.1` gives `["cave", "upper", "1"]`.
+    ///
+    /// The separator used by a Survex file is available from
+    /// [`SurveyData::separator`][`crate::data::SurveyData::separator`].
+    pub fn survey_parts(&self, separator: char) -> Vec<&str> {
+        self.label.split(separator).collect()
+    }
+
+    /// Retrieve the name of the station, which is the final component of its label. For example,
+    /// the name of `cave.upper.1` is `1`. A label with no separator is returned unchanged.
+    pub fn name(&self, separator: char) -> &str {
+        match self.label.rsplit_once(separator) {
+            Some((_, name)) => name,
+            None => &self.label,
+        }
+    }
+
+    /// Retrieve the survey which contains the station, which is its label without the final
+    /// component. For example, the parent survey of `cave.upper.1` is `cave.upper`. Returns
+    /// [`None`] if the label has no separator.
+    pub fn parent_survey(&self, separator: char) -> Option<&str> {
+        self.label.rsplit_once(separator).map(|(survey, _)| survey)
+    }
+
+    /// Whether the survey station is on the surface.
+    pub fn surface(&self) -> bool {
+        self.flags.contains(StationFlags::SURFACE)
+    }
+
+    /// Whether the survey station is underground.
+    pub fn underground(&self) -> bool {
+        self.flags.contains(StationFlags::UNDERGROUND)
+    }
+
+    /// Whether the survey station is an entrance.
+    pub fn entrance(&self) -> bool {
+        self.flags.contains(StationFlags::ENTRANCE)
+    }
+
+    /// Whether the survey station is exported.
+    pub fn exported(&self) -> bool {
+        self.flags.contains(StationFlags::EXPORTED)
+    }
+
+    /// Whether the survey station is fixed.
+    pub fn fixed(&self) -> bool {
+        self.flags.contains(StationFlags::FIXED)
+    }
+
+    /// Whether the survey station is anonymous.
+    pub fn anonymous(&self) -> bool {
+        self.flags.contains(StationFlags::ANONYMOUS)
+    }
+
+    /// Whether the survey station is a wall.
+    pub fn wall(&self) -> bool {
+        self.flags.contains(StationFlags::WALL)
+    }
+}
+
+bitflags! {
+    /// The flags which Survex can set on a survey station
+    ///
+    /// Each flag corresponds to one of the `img_SFLAG_*` constants in `img.h`, and has the same
+    /// value.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct StationFlags: i32 {
+        /// The station is on the surface (`img_SFLAG_SURFACE`).
+        const SURFACE = 0x01;
+        /// The station is underground (`img_SFLAG_UNDERGROUND`).
+        const UNDERGROUND = 0x02;
+        /// The station is an entrance (`img_SFLAG_ENTRANCE`).
+        const ENTRANCE = 0x04;
+        /// The station is exported (`img_SFLAG_EXPORTED`).
+        const EXPORTED = 0x08;
+        /// The station is fixed (`img_SFLAG_FIXED`).
+        const FIXED = 0x10;
+        /// The station is anonymous (`img_SFLAG_ANON`).
+        const ANONYMOUS = 0x20;
+        /// The station is on a passage wall (`img_SFLAG_WALL`).
+        const WALL = 0x40;
+    }
+}
+
+impl From<i32> for StationFlags {
+    /// Convert the `flags` value of an `img` struct, as set after reading a station label, into
+    /// [`StationFlags`]. Unrecognised flags are ignored.
+    fn from(flags: i32) -> Self {
+        Self::from_bits_truncate(flags)
+    }
 }
 
 impl Display for Station {
@@ -68,19 +163,29 @@ impl Display for Station {
 /// Passage dimension measurements
 ///
 /// LRUDs (Left, Right, Up, Down) are measurements taken from a station to the walls of a cave
-/// passage. The measurements are given in centimeters from the station to the wall and can be
-/// used to determine the volume of a passage.
+/// passage. The measurements are distances from the station to the wall in the given
+/// [`unit`][`LRUD::unit`] and can be used to determine the volume of a passage.
+///
+/// The Survex library gives LRUDs in the same unit as the survey coordinates, which is metres
+/// unless the coordinate system of the file specifies otherwise, so LRUDs read from a file have
+/// the same unit as [`SurveyData::units`][`crate::data::SurveyData::units`]. LRUDs created with
+/// [`LRUD::new`] are in metres, and [`LRUD::to_feet`] and [`LRUD::to_metres`] can be used to
+/// convert between units.
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LRUD {
     pub left: Option<f64>,
     pub right: Option<f64>,
     pub up: Option<f64>,
     pub down: Option<f64>,
+    /// The unit of the measurements.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unit: LengthUnit,
 }
 
 impl LRUD {
-    /// Create a new [`LRUD`] instance and update it with the given values. Usually, you will want
-    /// to use [`LRUD::update`] on an existing instance contained within a
+    /// Create a new [`LRUD`] instance in metres and update it with the given values. Usually, you
+    /// will want to use [`LRUD::update`] on an existing instance contained within a
     /// [`Station`][`crate::station::Station`] struct instead.
     pub fn new(left: f64, right: f64, up: f64, down: f64) -> Self {
         let mut lrud = Self::default();
@@ -88,7 +193,8 @@ impl LRUD {
         lrud
     }
 
-    /// Update the [`LRUD`] instance with the given values.
+    /// Update the [`LRUD`] instance with the given values, which must be in the unit of the
+    /// instance. Negative values are treated as missing measurements.
     pub fn update(&mut self, left: f64, right: f64, up: f64, down: f64) {
         let left = if left < 0.0 { None } else { Some(left) };
         let right = if right < 0.0 { None } else { Some(right) };
@@ -99,35 +205,210 @@ impl LRUD {
         self.up = up;
         self.down = down;
     }
+
+    /// Convert the measurements to the given unit.
+    pub fn convert(&self, unit: LengthUnit) -> LRUD {
+        let convert = |value: Option<f64>| value.map(|value| self.unit.convert(value, unit));
+        LRUD {
+            left: convert(self.left),
+            right: convert(self.right),
+            up: convert(self.up),
+            down: convert(self.down),
+            unit,
+        }
+    }
+
+    /// Convert the measurements to metres.
+    pub fn to_metres(&self) -> LRUD {
+        self.convert(LengthUnit::Metres)
+    }
+
+    /// Convert the measurements to feet.
+    pub fn to_feet(&self) -> LRUD {
+        self.convert(LengthUnit::Feet)
+    }
+
+    /// Estimate the cross-sectional area of the passage at the station in square
+    /// [units][`LRUD::unit`], or [`None`] if any of the four measurements is missing.
+    ///
+    /// The passage is modelled as a diamond made of four right-angled triangles, one in each
+    /// quadrant around the station, giving an area of `0.5 * (left + right) * (up + down)`. This
+    /// underestimates the area of rectangular passages and is closer for rounded ones.
+    pub fn area(&self) -> Option<f64> {
+        let (left, right, up, down) = (self.left?, self.right?, self.up?, self.down?);
+        Some(0.5 * (left + right) * (up + down))
+    }
 }
 
-/// A point in 3D space
-///
-/// Coordinates are given in metres.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+impl Display for LRUD {
+    /// Format the [`LRUD`] as a space-separated list of measurements, each prefixed with its
+    /// direction, e.g. `L1.00 R0.00 U0.30 D0.60`. Missing measurements are shown as `-`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let format = |value: Option<f64>| match value {
+            Some(value) => format!("{:.2}", value),
+            None => String::from("-"),
+        };
+        write!(
+            f,
+            "L{} R{} U{} D{}",
+            format(self.left),
+            format(self.right),
+            format(self.up),
+            format(self.down)
+        )
+    }
 }
 
-impl Point {
-    /// Create a new [`Point`] with the given coordinates.
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
-        Self { x, y, z }
+/// Serialize and deserialize a [`NodeIndex`] as the `usize` returned by [`NodeIndex::index`],
+/// for use with `#[serde(with = "node_index")]`.
+#[cfg(feature = "serde")]
+pub(crate) mod node_index {
+    use petgraph::graph::NodeIndex;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(index: &NodeIndex, s: S) -> Result<S::Ok, S::Error> {
+        index.index().serialize(s)
     }
 
-    /// Calculate the distance between two points. This is a simple Euclidean distance
-    /// calculation. The result is given in metres.
-    pub fn distance(&self, other: &Self) -> f64 {
-        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
-            .sqrt()
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<NodeIndex, D::Error> {
+        usize::deserialize(d).map(NodeIndex::new)
     }
 }
 
-impl Display for Point {
-    /// Format the [`Point`] as a comma-separated list of coordinates.
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:.2}, {:.2}, {:.2}", self.x, self.y, self.z)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lrud_display() {
+        let lrud = LRUD::new(1.0, 0.0, 0.3, 0.6);
+        assert_eq!(lrud.to_string(), "L1.00 R0.00 U0.30 D0.60");
+
+        let lrud = LRUD::new(-1.0, 2.5, -1.0, 9.0);
+        assert_eq!(lrud.to_string(), "L- R2.50 U- D9.00");
+    }
+
+    #[test]
+    fn test_lrud_conversion() {
+        let mut lrud = LRUD::new(1.0, 0.0, 0.3, -1.0);
+        assert_eq!(lrud.unit, LengthUnit::Metres);
+        assert_eq!(lrud.to_metres(), lrud);
+
+        let feet = lrud.to_feet();
+        assert_eq!(feet.unit, LengthUnit::Feet);
+        assert!((feet.left.unwrap() - 3.28084).abs() < 1e-5);
+        assert_eq!(feet.right, Some(0.0));
+        assert_eq!(feet.down, None);
+
+        let metres = feet.to_metres();
+        assert_eq!(metres.unit, LengthUnit::Metres);
+        for (a, b) in [(metres.left, lrud.left), (metres.up, lrud.up)] {
+            assert!((a.unwrap() - b.unwrap()).abs() < 1e-12);
+        }
+        assert_eq!(metres.down, None);
+
+        lrud.unit = LengthUnit::Feet;
+        assert_eq!(lrud.to_feet(), lrud);
+    }
+
+    #[test]
+    fn test_lrud_area() {
+        assert_eq!(LRUD::new(1.0, 1.0, 1.0, 1.0).area(), Some(2.0));
+        assert_eq!(LRUD::new(2.0, 0.0, 0.5, 1.5).area(), Some(2.0));
+        assert_eq!(LRUD::new(0.0, 0.0, 3.0, 3.0).area(), Some(0.0));
+        assert_eq!(LRUD::new(1.0, -1.0, 1.0, 1.0).area(), None);
+        assert_eq!(LRUD::default().area(), None);
+    }
+
+    #[test]
+    fn test_survey_parts() {
+        let station = |label: &str| {
+            Station::new(
+                String::from(label),
+                Point::new(0.0, 0.0, 0.0),
+                NodeIndex::new(0),
+            )
+        };
+
+        let s = station("nottsii.inlet5.inlet5-resurvey-4.22");
+        assert_eq!(
+            s.survey_parts('.'),
+            vec!["nottsii", "inlet5", "inlet5-resurvey-4", "22"]
+        );
+        assert_eq!(s.name('.'), "22");
+        assert_eq!(
+            s.parent_survey('.'),
+            Some("nottsii.inlet5.inlet5-resurvey-4")
+        );
+
+        let s = station("cave/upper/1");
+        assert_eq!(s.survey_parts('/'), vec!["cave", "upper", "1"]);
+        assert_eq!(s.name('/'), "1");
+        assert_eq!(s.parent_survey('/'), Some("cave/upper"));
+        assert_eq!(s.name('.'), "cave/upper/1");
+        assert_eq!(s.parent_survey('.'), None);
+
+        let s = station("1");
+        assert_eq!(s.survey_parts('.'), vec!["1"]);
+        assert_eq!(s.name('.'), "1");
+        assert_eq!(s.parent_survey('.'), None);
+    }
+
+    #[test]
+    fn test_station_flags() {
+        let station = |flags| {
+            let mut station = Station::new(
+                String::from("a.1"),
+                Point::new(0.0, 0.0, 0.0),
+                NodeIndex::new(0),
+            );
+            station.flags = StationFlags::from(flags);
+            station
+        };
+
+        let entrance = station(0x04 | 0x08 | 0x10);
+        assert!(!entrance.surface());
+        assert!(!entrance.underground());
+        assert!(entrance.entrance());
+        assert!(entrance.exported());
+        assert!(entrance.fixed());
+        assert!(!entrance.anonymous());
+        assert!(!entrance.wall());
+
+        let underground = station(0x02 | 0x08);
+        assert!(!underground.surface());
+        assert!(underground.underground());
+        assert!(!underground.entrance());
+        assert!(underground.exported());
+        assert!(!underground.fixed());
+        assert!(!underground.anonymous());
+        assert!(!underground.wall());
+
+        let surface = station(0x01);
+        assert!(surface.surface());
+        assert!(!surface.underground());
+        assert_eq!(surface.flags, StationFlags::SURFACE);
+
+        let all = station(0x7f | 0x80);
+        assert_eq!(all.flags, StationFlags::all());
+        assert!(all.anonymous() && all.wall());
+        assert_eq!(station(0).flags, StationFlags::empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_station_serde_round_trip() {
+        let mut station = Station::new(
+            String::from("nottsii.entrance"),
+            Point::new(66668.0, 78303.0, 319.0),
+            NodeIndex::new(42),
+        );
+        station.lrud = LRUD::new(1.0, 0.5, -1.0, 2.0);
+        station.flags.insert(StationFlags::ENTRANCE);
+
+        let json = serde_json::to_string(&station).unwrap();
+        assert!(json.contains("\"index\":42"));
+        let deserialized: Station = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, station);
     }
 }
@@ -0,0 +1,147 @@
+//! A thread-safe snapshot of processed Survex data
+//!
+//! [`SurveyData`] stores its stations as [`Rc<RefCell<Station>>`][`crate::data::RefStation`],
+//! which is cheap to clone and borrow but cannot be sent between threads. A [`SyncSurveyData`]
+//! instance, created with [`SurveyData::to_sync`], holds a copy of the same data with each
+//! station stored as an [`Arc<RwLock<Station>>`][`SyncRefStation`] instead, so that it can be
+//! moved into another thread or shared between threads, such as in a web server.
+//!
+//! Creating a snapshot copies every station, and locking a station is slower than borrowing one,
+//! so the snapshot only offers the survey data itself rather than the analysis methods of
+//! [`SurveyData`]. Use [`SyncSurveyData::to_survey_data`] to convert it back on the thread which
+//! needs them. Changes made to either copy are not reflected in the other.
+
+use crate::data::{StationGraph, SurveyData};
+use crate::leg::LoopError;
+use crate::point::{LengthUnit, Point};
+use crate::station::Station;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+pub type SyncStations = Vec<SyncRefStation>;
+pub type SyncRefStation = Arc<RwLock<Station>>;
+
+/// A thread-safe copy of a [`SurveyData`] instance
+///
+/// See the [module documentation][`crate::sync`] for details. The fields have the same meaning as
+/// the fields of [`SurveyData`], and each station in `stations` is at the position given by its
+/// index in `graph`.
+#[derive(Debug, Clone)]
+pub struct SyncSurveyData {
+    pub stations: SyncStations,
+    pub graph: StationGraph,
+    pub title: String,
+    pub coordinate_system: Option<String>,
+    pub datestamp: Option<String>,
+    pub timestamp: Option<SystemTime>,
+    pub format_version: i32,
+    pub separator: char,
+    pub extended_elevation: bool,
+    pub units: LengthUnit,
+    pub label_conflicts: Vec<(String, Point, Point)>,
+    pub loop_errors: Vec<LoopError>,
+}
+
+impl SurveyData {
+    /// Create a thread-safe copy of the survey. See the [`sync`][`crate::sync`] module for
+    /// details.
+    pub fn to_sync(&self) -> SyncSurveyData {
+        SyncSurveyData {
+            stations: self
+                .stations
+                .iter()
+                .map(|station| Arc::new(RwLock::new(station.borrow().clone())))
+                .collect(),
+            graph: self.graph.clone(),
+            title: self.title.clone(),
+            coordinate_system: self.coordinate_system.clone(),
+            datestamp: self.datestamp.clone(),
+            timestamp: self.timestamp,
+            format_version: self.format_version,
+            separator: self.separator,
+            extended_elevation: self.extended_elevation,
+            units: self.units,
+            label_conflicts: self.label_conflicts.clone(),
+            loop_errors: self.loop_errors.clone(),
+        }
+    }
+}
+
+impl SyncSurveyData {
+    /// Convert the snapshot back into a [`SurveyData`] instance, so that its analysis methods can
+    /// be used. The stations are copied, so this can be called on any thread which holds the
+    /// snapshot.
+    ///
+    /// # Panics
+    /// Panics if a station's lock has been poisoned by a thread which panicked while holding it.
+    pub fn to_survey_data(&self) -> SurveyData {
+        let mut data = SurveyData::new();
+        data.title = self.title.clone();
+        data.coordinate_system = self.coordinate_system.clone();
+        data.datestamp = self.datestamp.clone();
+        data.timestamp = self.timestamp;
+        data.format_version = self.format_version;
+        data.separator = self.separator;
+        data.extended_elevation = self.extended_elevation;
+        data.units = self.units;
+        data.label_conflicts = self.label_conflicts.clone();
+        data.loop_errors = self.loop_errors.clone();
+        for station in &self.stations {
+            let station = station.read().expect("Station lock was poisoned").clone();
+            data.push_station(station);
+        }
+        for edge in self.graph.raw_edges() {
+            data.graph
+                .add_edge(edge.source(), edge.target(), edge.weight);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::load_from_path;
+    use std::path::PathBuf;
+    use std::thread;
+
+    #[test]
+    fn test_to_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SyncSurveyData>();
+
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let sync = data.to_sync();
+
+        let (stations, length, label) = thread::spawn(move || {
+            let station = sync.stations[1901].read().unwrap();
+            let data = sync.to_survey_data();
+            (
+                sync.stations.len(),
+                data.total_length(true, true),
+                station.label.clone(),
+            )
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(stations, data.stations.len());
+        assert_eq!(length, data.total_length(true, true));
+        assert_eq!(label, "nottsii.entrance");
+    }
+
+    #[test]
+    fn test_to_survey_data() {
+        let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+        let copy = data.to_sync().to_survey_data();
+
+        assert_eq!(copy.title, data.title);
+        assert_eq!(copy.graph.edge_count(), data.graph.edge_count());
+        assert_eq!(copy.loop_errors(), data.loop_errors());
+        for (a, b) in data.stations.iter().zip(copy.stations.iter()) {
+            assert_eq!(*a.borrow(), *b.borrow());
+        }
+        let station = copy.get_by_label("nottsii.entrance").unwrap();
+        assert_eq!(station.borrow().index.index(), 1901);
+    }
+}
@@ -0,0 +1,239 @@
+//! Helper functions for writing Survex files
+//!
+//! The main entry point is [`save_to_path`][`crate::write::save_to_path`], which writes a
+//! [`SurveyData`] instance to a Survex .3d file so that it can be read again by this crate or by
+//! other Survex tools such as Aven.
+
+use crate::data::SurveyData;
+use crate::error::WriteError;
+use crate::leg::UNIX_EPOCH_DAYS;
+use crate::point::Point;
+use crate::survex;
+use log::trace;
+use petgraph::visit::EdgeRef;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::ptr;
+
+/// Write a [`SurveyData`] instance to a Survex .3d file at the given path, replacing the file if
+/// it already exists.
+///
+/// The title, coordinate system and [extended elevation][`SurveyData::extended_elevation`] flag
+/// of the survey are written to the file header. Every leg is written with its style, flags and
+/// survey dates, followed by every station with its flags and any
+/// [`LRUD`][`crate::station::LRUD`] measurements, converted to the
+/// [units][`SurveyData::units`] of the survey coordinates. Station flags include any
+/// [raw flags][`crate::station::Station::raw_flags`] which are not understood by this crate.
+/// Anonymous stations are written without a label, so they will be given a new label when the
+/// file is read. Loop closure errors and the datestamp are not written, and the Survex library
+/// records the current time as the date of the file.
+///
+/// ```rust
+/// use std::path::PathBuf;
+/// use survex_rs::read::load_from_path;
+/// use survex_rs::write::save_to_path;
+///
+/// let data = load_from_path(PathBuf::from("tests/data/nottsii.3d")).unwrap();
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("copy.3d");
+/// save_to_path(&data, path.clone()).unwrap();
+///
+/// let copy = load_from_path(path).unwrap();
+/// assert_eq!(copy.stations.len(), data.stations.len());
+/// ```
+pub fn save_to_path(data: &SurveyData, path: PathBuf) -> Result<(), WriteError> {
+    // Convert the path and header strings to the format required by img.c
+    let filename = path
+        .to_str()
+        .and_then(|path| CString::new(path).ok())
+        .ok_or_else(|| WriteError::InvalidPath(path.clone()))?;
+    let title = c_string(&data.title)?;
+    let coordinate_system = data
+        .coordinate_system
+        .as_deref()
+        .map(c_string)
+        .transpose()?;
+    let flags = if data.extended_elevation { 0x80 } else { 0 };
+
+    trace!("Opening Survex file '{:?}' for writing.", path);
+    let pimg = unsafe {
+        survex::img_open_write_cs(
+            filename.as_ptr(),
+            title.as_ptr(),
+            coordinate_system
+                .as_ref()
+                .map_or(ptr::null(), |cs| cs.as_ptr()),
+            flags,
+        )
+    };
+    if pimg.is_null() {
+        trace!("Survex library returned a null pointer. Write failed.");
+        return Err(WriteError::OpenFailed);
+    }
+
+    // Write the data and then close the file, regardless of whether writing was successful.
+    // Closing the file writes the end of data marker and reports any error which occurred while
+    // writing.
+    let result = write_survey(pimg, data);
+    let closed = unsafe { survex::img_close(pimg) } != 0;
+    result?;
+    if !closed {
+        trace!("Survex library reported an error closing the file.");
+        return Err(WriteError::WriteFailed);
+    }
+    Ok(())
+}
+
+/// Write the legs and stations of the survey to an img struct which has been opened for
+/// writing.
+fn write_survey(pimg: *mut survex::img, data: &SurveyData) -> Result<(), WriteError> {
+    // Each leg is written as a MOVE to its first station followed by a LINE to its second
+    // station. The MOVE can be skipped if the previous leg ended at the first station.
+    let mut position: Option<Point> = None;
+    for edge in data.graph.edge_references() {
+        let (Some(from), Some(to)) = (
+            data.get_by_index(edge.source()),
+            data.get_by_index(edge.target()),
+        ) else {
+            continue;
+        };
+        let survey = c_string(&data.leg_survey(&from, &to).unwrap_or_default())?;
+        let (from, to) = (from.borrow().coords, to.borrow().coords);
+        let leg = edge.weight();
+        unsafe {
+            if position != Some(from) {
+                survex::img_write_item(pimg, 0, 0, ptr::null(), from.x, from.y, from.z);
+            }
+            (*pimg).style = i32::from(leg.style);
+            let (date1, date2) = leg.days.map_or((0, 0), |(first, last)| {
+                (date_from_days(first), date_from_days(last))
+            });
+            (*pimg).date1 = date1 as survex::time_t;
+            (*pimg).date2 = date2 as survex::time_t;
+            survex::img_write_item(
+                pimg,
+                1,
+                i32::from(leg.flags),
+                survey.as_ptr(),
+                to.x,
+                to.y,
+                to.z,
+            );
+        }
+        position = Some(to);
+    }
+    trace!("Wrote {} legs.", data.graph.edge_count());
+
+    for station in data.iter_stations() {
+        let label = if station.anonymous() {
+            c_string(station.original_label.as_deref().unwrap_or_default())?
+        } else {
+            c_string(&station.label)?
+        };
+        let coords = station.coords;
+        unsafe {
+            survex::img_write_item(
+                pimg,
+                3,
+                station.flags.bits() | i32::from(station.raw_flags),
+                label.as_ptr(),
+                coords.x,
+                coords.y,
+                coords.z,
+            );
+        }
+    }
+    trace!("Wrote {} stations.", data.stations.len());
+
+    // Cross-sections are written as a separate passage for each station, as the order in which
+    // they were originally given is not stored. Missing measurements are written as -1.
+    for station in data.iter_stations() {
+        let lrud = station.lrud.convert(data.units);
+        if station.anonymous()
+            || [lrud.left, lrud.right, lrud.up, lrud.down]
+                .iter()
+                .all(Option::is_none)
+        {
+            continue;
+        }
+        let label = c_string(&station.label)?;
+        unsafe {
+            (*pimg).l = lrud.left.unwrap_or(-1.0);
+            (*pimg).r = lrud.right.unwrap_or(-1.0);
+            (*pimg).u = lrud.up.unwrap_or(-1.0);
+            (*pimg).d = lrud.down.unwrap_or(-1.0);
+            survex::img_write_item(pimg, 4, 0x01, label.as_ptr(), 0.0, 0.0, 0.0);
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert a Survex day number into seconds since the Unix epoch, as used by the Survex library
+/// for survey dates.
+fn date_from_days(days: i32) -> i64 {
+    (i64::from(days) - UNIX_EPOCH_DAYS) * 86400
+}
+
+/// Convert a string into a [`CString`] which can be passed to the Survex library, returning an
+/// error if it contains a NUL byte.
+fn c_string(s: &str) -> Result<CString, WriteError> {
+    CString::new(s).map_err(|_| WriteError::InvalidString(String::from(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::load_from_path;
+
+    #[test]
+    fn test_save_to_path() {
+        let data = load_from_path(PathBuf::from("tests/data/0733.3d")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("0733.3d");
+        save_to_path(&data, path.clone()).unwrap();
+
+        let copy = load_from_path(path).unwrap();
+        assert_eq!(copy.stations.len(), data.stations.len());
+        assert_eq!(copy.graph.edge_count(), data.graph.edge_count());
+        assert_eq!(copy.title, data.title);
+        assert_eq!(copy.coordinate_system, data.coordinate_system);
+        assert!(
+            (copy.total_length(true, true) - data.total_length(true, true)).abs() < 0.01,
+            "{} != {}",
+            copy.total_length(true, true),
+            data.total_length(true, true)
+        );
+
+        for station in data.iter_stations() {
+            let other = copy.get_by_label(&station.label).unwrap();
+            let other = other.borrow();
+            assert!(station.coords.distance(&other.coords) < 0.01);
+            assert_eq!(station.flags, other.flags);
+            assert_eq!(station.lrud, other.lrud);
+        }
+
+        let days = |data: &SurveyData| {
+            let mut days = data
+                .graph
+                .edge_weights()
+                .map(|leg| leg.days)
+                .collect::<Vec<_>>();
+            days.sort();
+            days
+        };
+        assert_eq!(days(&copy), days(&data));
+    }
+
+    #[test]
+    fn test_save_to_path_errors() {
+        let mut data = SurveyData::new();
+        data.title = String::from("bad\0title");
+        let dir = tempfile::tempdir().unwrap();
+        let result = save_to_path(&data, dir.path().join("bad.3d"));
+        assert!(matches!(result, Err(WriteError::InvalidString(_))));
+
+        let result = save_to_path(&SurveyData::new(), dir.path().join("missing/bad.3d"));
+        assert!(matches!(result, Err(WriteError::OpenFailed)));
+    }
+}